@@ -0,0 +1,197 @@
+use std::{
+    collections::HashMap,
+    fs::{create_dir, create_dir_all, remove_file, rename, write, File},
+    io::BufReader,
+};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use flate2::bufread::GzDecoder;
+use oci_spec::image::{Descriptor, HistoryBuilder, ImageIndex, MediaType, ANNOTATION_REF_NAME};
+use tar::Archive;
+
+use crate::{
+    container::{
+        changes::{changes, ChangeKind},
+        layer::write_layer,
+        namespaces::{self, NetworkNamespace},
+        spec::Spec,
+        state::State,
+    },
+    image::{parse_image_id, write_blob, ConfigChange, Image, ImageId},
+    util::{blob_path, copy_dir, generate_id, timestamp},
+};
+
+/// Create a new image from a container's changes
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Commit {
+    /// Apply Dockerfile instruction to the image configuration
+    /// (CMD, ENTRYPOINT, ENV, EXPOSE, LABEL, USER, VOLUME, WORKDIR)
+    #[clap(short, long, multiple_occurrences(true), number_of_values = 1)]
+    change: Vec<ConfigChange>,
+
+    /// Commit message
+    #[clap(short, long)]
+    message: Option<String>,
+
+    /// Container name or id
+    #[clap(name = "CONTAINER")]
+    container: String,
+
+    #[clap(name = "IMAGE", parse(from_str = parse_image_id))]
+    image_id: ImageId,
+}
+
+impl Commit {
+    pub fn exec(self) -> Result<()> {
+        let state = State::find(&self.container)?;
+        let upperdir = match state.upperdir()? {
+            Some(upperdir) => upperdir,
+            None => bail!("Containers can be committed only with overlay storage drivers"),
+        };
+
+        let spec = Spec::load(&state)?;
+        let image = Image::new(spec.image.name, spec.image.tag, spec.image_path)?;
+
+        // deletions would be stored as OCI whiteout files, which overlay
+        // drivers do not hide once the layer is unpacked
+        if let Some(change) = changes(&upperdir, &image.layer_paths())?
+            .into_iter()
+            .find(|change| change.kind == ChangeKind::Deleted)
+        {
+            bail!(
+                "Containers with deleted files can not be committed, {} was deleted",
+                change.path.display()
+            );
+        }
+
+        let base_path = std::env::current_dir()?.join(&self.image_id.name);
+        create_dir_all(&base_path)?;
+
+        // layers of the base image are shared with it unless it is in the
+        // same directory
+        for (layer, layer_path) in image.manifest.layers().iter().zip(image.layer_paths()) {
+            let path = blob_path(&base_path, layer.digest());
+            if !path.exists() {
+                create_dir_all(&path)?;
+                copy_dir(&layer_path, &path)?;
+            }
+        }
+
+        // owners in the layer are the ones seen inside of the container
+        let mappings = namespaces::Config::new(
+            spec.uid_mappings.clone(),
+            spec.gid_mappings.clone(),
+            NetworkNamespace::Host,
+        )?;
+
+        let blobs_dir = base_path.join("blobs/sha256");
+        create_dir_all(&blobs_dir)?;
+        let tar_gz_path = blobs_dir.join(format!("{}.tar.gz", generate_id()?));
+        let layer = write_layer(
+            &upperdir,
+            &tar_gz_path,
+            &mappings.uid_mappings,
+            &mappings.gid_mappings,
+        )?;
+
+        // layers are stored unpacked, same as the pulled ones
+        let layer_digest = format!("sha256:{}", layer.digest);
+        let layer_path = blob_path(&base_path, &layer_digest);
+        if !layer_path.exists() {
+            let unpacked_path = blobs_dir.join(format!("{}-unpacked", layer.digest));
+            create_dir(&unpacked_path)?;
+            Archive::new(GzDecoder::new(BufReader::new(File::open(&tar_gz_path)?)))
+                .unpack(&unpacked_path)?;
+            rename(&unpacked_path, &layer_path)?;
+        }
+        remove_file(&tar_gz_path)?;
+
+        let created = timestamp();
+        let mut configuration = image.configuration.clone();
+
+        let mut config = configuration.config().clone().unwrap_or_default();
+        for change in &self.change {
+            change.apply(&mut config);
+        }
+        configuration.set_config(Some(config));
+
+        let mut rootfs = configuration.rootfs().clone();
+        let mut diff_ids = rootfs.diff_ids().clone();
+        diff_ids.push(format!("sha256:{}", layer.diff_id));
+        rootfs.set_diff_ids(diff_ids);
+        configuration.set_rootfs(rootfs);
+
+        let mut history_builder = HistoryBuilder::default()
+            .created(created.clone())
+            .created_by(format!("con commit {}", state.name));
+        if let Some(message) = &self.message {
+            history_builder = history_builder.comment(message.clone());
+        }
+        let mut history = configuration.history().clone();
+        history.push(history_builder.build()?);
+        configuration.set_history(history);
+        configuration.set_created(Some(created));
+
+        let configuration_json = serde_json::to_vec(&configuration)?;
+        let configuration_digest = write_blob(&base_path, &configuration_json)?;
+
+        let mut manifest = image.manifest.clone();
+        manifest.set_config(Descriptor::new(
+            MediaType::ImageConfig,
+            configuration_json.len() as i64,
+            configuration_digest,
+        ));
+        let mut layers = manifest.layers().clone();
+        layers.push(Descriptor::new(
+            MediaType::ImageLayerGzip,
+            layer.size as i64,
+            layer_digest,
+        ));
+        manifest.set_layers(layers);
+
+        let manifest_json = serde_json::to_vec(&manifest)?;
+        let manifest_digest = write_blob(&base_path, &manifest_json)?;
+
+        let mut manifest_descriptor = Descriptor::new(
+            MediaType::ImageManifest,
+            manifest_json.len() as i64,
+            manifest_digest.clone(),
+        );
+        manifest_descriptor.set_annotations(Some(HashMap::from([(
+            ANNOTATION_REF_NAME.to_string(),
+            self.image_id.tag.clone(),
+        )])));
+
+        // manifest replaces the one with the same tag
+        let index_path = base_path.join("index.json");
+        let mut index = if index_path.exists() {
+            ImageIndex::from_file(&index_path)?
+        } else {
+            let mut index = image.index.clone();
+            index.set_manifests(vec![]);
+            index
+        };
+        let mut manifests = index.manifests().clone();
+        manifests.retain(|manifest| {
+            manifest
+                .annotations()
+                .as_ref()
+                .and_then(|annotations| annotations.get(ANNOTATION_REF_NAME))
+                != Some(&self.image_id.tag)
+        });
+        manifests.push(manifest_descriptor);
+        index.set_manifests(manifests);
+        write(&index_path, serde_json::to_string_pretty(&index)?)?;
+
+        let layout_path = base_path.join("oci-layout");
+        if !layout_path.exists() {
+            write(layout_path, r#"{"imageLayoutVersion":"1.0.0"}"#)?;
+        }
+
+        println!("{}", manifest_digest);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,462 @@
+use std::{
+    fs::{
+        copy, create_dir, hard_link, read_dir, read_link, remove_file, set_permissions,
+        symlink_metadata, File, Permissions,
+    },
+    io::{self, Read, Write},
+    os::unix::fs::{symlink, MetadataExt, PermissionsExt},
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use nix::{
+    sys::wait::WaitStatus,
+    unistd::{fchownat, FchownatFlags, Gid, Uid},
+};
+use tar::{Archive, Builder, EntryType, Header};
+
+use crate::container::{
+    fs::ContainerFs,
+    namespaces::{self, NetworkNamespace},
+    spec::Spec,
+    state::State,
+};
+
+/// Copy files between a container and the local file system
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Cp {
+    /// Keep owners of copied files, otherwise files copied into the
+    /// container are owned by its root user
+    #[clap(short, long)]
+    archive: bool,
+
+    /// CONTAINER:PATH, local path or - to read tar archive from standard input
+    #[clap(name = "SRC_PATH")]
+    source: Location,
+
+    /// CONTAINER:PATH, local path or - to write tar archive to standard output
+    #[clap(name = "DEST_PATH")]
+    destination: Location,
+}
+
+#[derive(Debug)]
+enum Location {
+    Container(String, PathBuf),
+    Local(PathBuf),
+    Stream,
+}
+
+impl FromStr for Location {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "-" {
+            return Ok(Self::Stream);
+        }
+
+        // local paths with colon can be written as ./name:with:colons
+        if s.starts_with('/') || s.starts_with('.') {
+            return Ok(Self::Local(PathBuf::from(s)));
+        }
+
+        match s.split_once(":") {
+            Some(("", _)) => Err("Container name or id is missing before the path"),
+            Some((container, path)) => Ok(Self::Container(
+                container.to_string(),
+                Path::new("/").join(path),
+            )),
+            None => Ok(Self::Local(PathBuf::from(s))),
+        }
+    }
+}
+
+/// Content of an entry copied into the container.
+enum Content<'a> {
+    Directory,
+    File(&'a mut dyn Read),
+    Symlink(PathBuf),
+    /// Link to entry (relative to the root) copied before
+    HardLink(PathBuf),
+}
+
+/// Container the files are copied from or to.
+struct Container<'a> {
+    fs: &'a ContainerFs,
+    /// Directories with their modes applied once their content is written
+    directories: Vec<(PathBuf, u32)>,
+}
+
+impl Cp {
+    pub fn exec(self) -> Result<()> {
+        let name = match (&self.source, &self.destination) {
+            (Location::Container(name, _), Location::Local(_) | Location::Stream)
+            | (Location::Local(_) | Location::Stream, Location::Container(name, _)) => name,
+            _ => bail!("Exactly one of source and destination has to be a container path"),
+        };
+
+        let state = State::find(name)?;
+        let spec = Spec::load(&state)?;
+        let fs = ContainerFs::new(&state, &spec)?;
+        let config = namespaces::Config::new(
+            spec.uid_mappings.clone(),
+            spec.gid_mappings.clone(),
+            NetworkNamespace::Host,
+        )?;
+
+        // files are accessed as root of the container's user namespace, so
+        // that rootless user can write files owned by subordinate ids and
+        // owners are the ones seen inside of the container
+        let status = namespaces::run(&config, || self.copy(&fs).map(|_| 0), |_| Ok(()))?;
+        if !matches!(status, WaitStatus::Exited(_, 0)) {
+            bail!(
+                "Copying process exited with code {}",
+                namespaces::exit_code(status)
+            );
+        }
+
+        Ok(())
+    }
+
+    fn copy(&self, fs: &ContainerFs) -> Result<()> {
+        let mut container = Container {
+            fs,
+            directories: vec![],
+        };
+
+        match (&self.source, &self.destination) {
+            (Location::Container(_, path), Location::Local(local)) => {
+                container.copy_to_local(path, local, self.archive)
+            }
+            (Location::Container(_, path), Location::Stream) => {
+                container.write_tar(path, io::stdout().lock())
+            }
+            (Location::Local(local), Location::Container(_, path)) => {
+                container.copy_from_local(local, path, self.archive)
+            }
+            (Location::Stream, Location::Container(_, path)) => {
+                container.read_tar(io::stdin().lock(), path, self.archive)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Container<'_> {
+    /// Resolved container path and host path of the existing entry.
+    fn find(&self, path: &Path, follow: bool) -> Result<(PathBuf, PathBuf)> {
+        let path = self.fs.resolve(path, follow)?;
+        match self.fs.lookup(&path)? {
+            Some((source, _)) => Ok((path, source)),
+            None => bail!(
+                "Path {} does not exist in the container",
+                Path::new("/").join(path).display()
+            ),
+        }
+    }
+
+    fn copy_to_local(&self, path: &Path, local: &Path, archive: bool) -> Result<()> {
+        let (path, source) = self.find(path, false)?;
+        let is_dir = symlink_metadata(&source)?.is_dir();
+
+        // content of the root is merged into existing directory
+        let target = match path.file_name() {
+            Some(name) if local.is_dir() => local.join(name),
+            _ => local.to_path_buf(),
+        };
+
+        match symlink_metadata(&target) {
+            Ok(metadata) if is_dir && !metadata.is_dir() => {
+                bail!("Can not copy directory over file {}", target.display())
+            }
+            Ok(metadata) if !is_dir && metadata.is_dir() => {
+                bail!("Can not copy file over directory {}", target.display())
+            }
+            Err(_) => {
+                let parent = match target.parent() {
+                    Some(parent) if !parent.as_os_str().is_empty() => parent,
+                    _ => Path::new("."),
+                };
+                if !parent.is_dir() {
+                    bail!("Directory {} does not exist", parent.display());
+                }
+            }
+            _ => {}
+        }
+
+        self.copy_entry_to_local(&path, &target, archive)
+    }
+
+    fn copy_entry_to_local(&self, path: &Path, target: &Path, archive: bool) -> Result<()> {
+        let (source, metadata) = match self.fs.lookup(path)? {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        let file_type = metadata.file_type();
+
+        if file_type.is_dir() {
+            if !target.is_dir() {
+                create_dir(target)?;
+            }
+            for name in self.fs.read_dir(path)? {
+                self.copy_entry_to_local(&path.join(&name), &target.join(&name), archive)?;
+            }
+        } else if file_type.is_symlink() {
+            if symlink_metadata(target).is_ok() {
+                remove_file(target)?;
+            }
+            symlink(read_link(&source)?, target)?;
+        } else if file_type.is_file() {
+            if matches!(symlink_metadata(target), Ok(existing) if existing.file_type().is_symlink())
+            {
+                remove_file(target)?;
+            }
+            copy(&source, target)?;
+        } else {
+            return Ok(());
+        }
+
+        if !file_type.is_symlink() {
+            set_permissions(target, metadata.permissions())?;
+        }
+
+        if archive {
+            chown(target, metadata.uid(), metadata.gid())?;
+        }
+
+        Ok(())
+    }
+
+    fn write_tar<W: Write>(&self, path: &Path, writer: W) -> Result<()> {
+        let (path, _) = self.find(path, false)?;
+        let name = path.file_name().map_or(PathBuf::from("."), PathBuf::from);
+
+        let mut builder = Builder::new(writer);
+        builder.follow_symlinks(false);
+        self.append_entry(&mut builder, &path, &name)?;
+        builder.into_inner()?.flush()?;
+
+        Ok(())
+    }
+
+    fn append_entry<W: Write>(
+        &self,
+        builder: &mut Builder<W>,
+        path: &Path,
+        name: &Path,
+    ) -> Result<()> {
+        let (source, metadata) = match self.fs.lookup(path)? {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        let file_type = metadata.file_type();
+
+        let mut header = Header::new_gnu();
+        header.set_metadata(&metadata);
+
+        if file_type.is_dir() {
+            builder.append_data(&mut header, name, io::empty())?;
+            for child in self.fs.read_dir(path)? {
+                self.append_entry(builder, &path.join(&child), &name.join(&child))?;
+            }
+        } else if file_type.is_symlink() {
+            builder.append_link(&mut header, name, read_link(&source)?)?;
+        } else if file_type.is_file() {
+            builder.append_data(&mut header, name, File::open(&source)?)?;
+        }
+
+        Ok(())
+    }
+
+    fn copy_from_local(&mut self, local: &Path, path: &Path, archive: bool) -> Result<()> {
+        let metadata = symlink_metadata(local)?;
+        // symbolic link to directory is followed to copy into it
+        let path = self.fs.resolve(path, true)?;
+
+        let target = match self.fs.lookup(&path)? {
+            Some((_, existing)) if existing.is_dir() => match local.canonicalize()?.file_name() {
+                Some(name) => path.join(name),
+                None => path,
+            },
+            Some(_) if metadata.is_dir() => bail!(
+                "Can not copy directory over file {}",
+                Path::new("/").join(&path).display()
+            ),
+            Some(_) => path,
+            None => match path.parent().map(|parent| self.fs.lookup(parent)) {
+                Some(Ok(Some((_, parent)))) if parent.is_dir() => path,
+                _ => bail!(
+                    "Parent directory of {} does not exist in the container",
+                    Path::new("/").join(&path).display()
+                ),
+            },
+        };
+
+        self.copy_local_entry(local, &target, archive)?;
+        self.finish()
+    }
+
+    fn copy_local_entry(&mut self, local: &Path, path: &Path, archive: bool) -> Result<()> {
+        let metadata = symlink_metadata(local)?;
+        let file_type = metadata.file_type();
+        let (uid, gid) = if archive {
+            (metadata.uid(), metadata.gid())
+        } else {
+            (0, 0)
+        };
+
+        if file_type.is_dir() {
+            self.write_entry(path, Content::Directory, metadata.mode(), uid, gid)?;
+            for entry in read_dir(local)? {
+                let entry = entry?;
+                self.copy_local_entry(&entry.path(), &path.join(entry.file_name()), archive)?;
+            }
+        } else if file_type.is_symlink() {
+            let content = Content::Symlink(read_link(local)?);
+            self.write_entry(path, content, metadata.mode(), uid, gid)?;
+        } else if file_type.is_file() {
+            let mut file = File::open(local)?;
+            self.write_entry(path, Content::File(&mut file), metadata.mode(), uid, gid)?;
+        }
+
+        Ok(())
+    }
+
+    fn read_tar<R: Read>(&mut self, reader: R, path: &Path, archive: bool) -> Result<()> {
+        let (path, source) = self.find(path, true)?;
+        if !symlink_metadata(source)?.is_dir() {
+            bail!(
+                "Archive can be extracted only into directory, {} is not one",
+                Path::new("/").join(&path).display()
+            );
+        }
+
+        let mut tar = Archive::new(reader);
+        for entry in tar.entries()? {
+            let mut entry = entry?;
+            let name = entry_path(&entry.path()?)?;
+            let header = entry.header();
+            let mode = header.mode()?;
+            let (uid, gid) = if archive {
+                (header.uid()? as u32, header.gid()? as u32)
+            } else {
+                (0, 0)
+            };
+
+            let entry_type = header.entry_type();
+            let link_name = entry.link_name()?.map(|link| link.into_owned());
+            let content = match (entry_type, link_name) {
+                (EntryType::Directory, _) => Content::Directory,
+                (EntryType::Regular | EntryType::Continuous, _) => Content::File(&mut entry),
+                (EntryType::Symlink, Some(target)) => Content::Symlink(target),
+                (EntryType::Link, Some(target)) => {
+                    Content::HardLink(path.join(entry_path(&target)?))
+                }
+                _ => continue,
+            };
+
+            self.write_entry(&path.join(name), content, mode, uid, gid)?;
+        }
+
+        self.finish()
+    }
+
+    /// Writes entry at `path` (relative to the root) owned by container
+    /// ids. Directories are merged with the existing ones.
+    fn write_entry(
+        &mut self,
+        path: &Path,
+        content: Content,
+        mode: u32,
+        uid: u32,
+        gid: u32,
+    ) -> Result<()> {
+        // symbolic links written before must not lead out of the container
+        let mut path = self.fs.resolve(path, false)?;
+        if matches!(content, Content::Directory) {
+            let target = self.fs.resolve(&path, true)?;
+            if matches!(self.fs.lookup(&target)?, Some((_, existing)) if existing.is_dir()) {
+                path = target;
+            }
+        }
+        let path = path.as_path();
+
+        if let Some((_, existing)) = self.fs.lookup(path)? {
+            if existing.is_dir() && !matches!(content, Content::Directory) {
+                bail!(
+                    "Can not copy file over directory {}",
+                    Path::new("/").join(path).display()
+                );
+            }
+        }
+
+        let host_path = self.fs.create_path(path)?;
+
+        match content {
+            Content::Directory => {
+                if !host_path.is_dir() {
+                    create_dir(&host_path)?;
+                }
+                // content is written before the directory becomes read-only
+                set_permissions(&host_path, Permissions::from_mode(0o700))?;
+                self.directories.push((host_path.clone(), mode));
+            }
+            Content::File(reader) => {
+                io::copy(reader, &mut File::create(&host_path)?)?;
+                set_permissions(&host_path, Permissions::from_mode(mode & 0o7777))?;
+            }
+            Content::Symlink(target) => symlink(target, &host_path)?,
+            Content::HardLink(target) => match self.fs.lookup(&self.fs.resolve(&target, false)?)? {
+                Some((source, _)) => hard_link(source, &host_path)?,
+                None => bail!("Link target {} was not copied", target.display()),
+            },
+        }
+
+        chown(&host_path, uid, gid)
+    }
+
+    /// Applies modes of the written directories, the nested ones first.
+    fn finish(&mut self) -> Result<()> {
+        while let Some((path, mode)) = self.directories.pop() {
+            set_permissions(path, Permissions::from_mode(mode & 0o7777))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Path of archive entry relative to the destination, entries leaving it
+/// are rejected.
+fn entry_path(path: &Path) -> Result<PathBuf> {
+    let mut result = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => result.push(name),
+            Component::CurDir => {}
+            _ => bail!("Invalid path {} in archive", path.display()),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Changes owner of `path` without following symbolic links, unless it is
+/// already owned by the ids.
+fn chown(path: &Path, uid: u32, gid: u32) -> Result<()> {
+    let metadata = symlink_metadata(path)?;
+    if metadata.uid() == uid && metadata.gid() == gid {
+        return Ok(());
+    }
+
+    fchownat(
+        None,
+        path,
+        Some(Uid::from_raw(uid)),
+        Some(Gid::from_raw(gid)),
+        FchownatFlags::NoFollowSymlink,
+    )?;
+
+    Ok(())
+}
@@ -0,0 +1,354 @@
+use std::{
+    net::IpAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    container::{
+        bundle::Bundle,
+        cgroups,
+        devices::Device,
+        env::EnvVariable,
+        hosts::HostEntry,
+        idmap::IdMap,
+        ipam::Subnet,
+        network::NetworkMode,
+        ports::PortMapping,
+        security::{self, SecurityOpt},
+        spec::Spec,
+        state::State,
+        storage::{StorageDriver, StorageOpt},
+    },
+    image::{parse_image_id, Image, ImageId},
+    util::parse_size,
+    volume::{parse_mount, parse_tmpfs, Volume},
+};
+use anyhow::{anyhow, bail, Result};
+use clap::Parser;
+use nix::unistd::getuid;
+
+use super::pull::Pull;
+
+const READ_ONLY_TMPFS_PATHS: [&str; 2] = ["/run", "/var/tmp"];
+
+/// Create a new container without starting it
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Create {
+    /// Assign a name to the container
+    #[clap(long)]
+    name: Option<String>,
+
+    /// Automatically remove the container and its anonymous volumes when it
+    /// exits
+    #[clap(long)]
+    rm: bool,
+
+    /// Container host name
+    #[clap(long, default_value = "container")]
+    hostname: String,
+
+    /// Container NIS domain name
+    #[clap(long)]
+    domainname: Option<String>,
+
+    /// Set custom DNS servers
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    dns: Vec<IpAddr>,
+
+    /// Set custom DNS search domains
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    dns_search: Vec<String>,
+
+    /// Add a custom host-to-IP mapping (host:ip)
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    add_host: Vec<HostEntry>,
+
+    #[clap(flatten)]
+    cgroups_config: cgroups::Config,
+
+    /// Size of /dev/shm (e.g. 64m)
+    #[clap(long, default_value = "64m", parse(try_from_str = parse_size))]
+    shm_size: u64,
+
+    /// Network mode (none, private, host, container:<name|id>) or name of the
+    /// network to connect to (e.g. bridge), private:allow_host_loopback lets
+    /// container connect to host's loopback through gateway 10.0.2.2
+    #[clap(long, default_value = "private")]
+    network: NetworkMode,
+
+    /// Subnet of the default bridge network when it is created (e.g. 10.88.0.0/16)
+    #[clap(long)]
+    subnet: Option<Subnet>,
+
+    /// Publish a container's port to the host
+    /// ([host_ip:][host_port:]container_port[/protocol]), -p used to be short
+    /// for --pids-limit
+    #[clap(short = 'p', long, multiple_occurrences(true), number_of_values = 1)]
+    publish: Vec<PortMapping>,
+
+    /// Publish all exposed ports to free host ports
+    #[clap(short = 'P', long)]
+    publish_all: bool,
+
+    /// Add a host device to the container (host_path[:container_path][:permissions])
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    device: Vec<Device>,
+
+    /// Bind mount a volume (source|name:destination[:options])
+    #[clap(short, long, multiple_occurrences(true), number_of_values = 1)]
+    volumes: Vec<Volume>,
+
+    /// Mount a tmpfs directory (destination[:options])
+    #[clap(long, multiple_occurrences(true), number_of_values = 1, parse(try_from_str = parse_tmpfs))]
+    tmpfs: Vec<Volume>,
+
+    /// Attach a filesystem mount
+    /// (type=bind|volume|tmpfs,source=...,target=...[,readonly,...])
+    #[clap(long, multiple_occurrences(true), number_of_values = 1, parse(try_from_str = parse_mount))]
+    mount: Vec<Volume>,
+
+    /// Mount the container's root file system as read only
+    #[clap(long)]
+    read_only: bool,
+
+    /// Mount writable tmpfs at /run and /var/tmp when root file system is
+    /// read only
+    #[clap(long, default_value = "true", parse(try_from_str))]
+    read_only_tmpfs: bool,
+
+    /// Storage driver used for the root file system (overlay, fuse-overlayfs,
+    /// vfs), detected if not set
+    #[clap(long)]
+    storage_driver: Option<StorageDriver>,
+
+    /// Storage options (size=<size> limits the writable layer, e.g. size=2g,
+    /// such layer is kept in memory and discarded when the container stops)
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    storage_opt: Vec<StorageOpt>,
+
+    /// Set environment variables
+    #[clap(short, long, multiple_occurrences(true), number_of_values = 1)]
+    env: Vec<EnvVariable>,
+
+    /// Security options (no-new-privileges[=true|false], systempaths=unconfined,
+    /// mask=path[:path], unmask=ALL|path[:path], readonly=path[:path])
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    security_opt: Vec<SecurityOpt>,
+
+    /// Username or UID (format: name|uid[:group|gid])
+    #[clap(short, long)]
+    user: Option<String>,
+
+    /// Working directory inside the container
+    #[clap(short, long)]
+    workdir: Option<PathBuf>,
+
+    /// Add additional groups to join
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    group_add: Vec<String>,
+
+    /// UID mapping for user namespace (container_id:host_id:size)
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    uidmap: Vec<IdMap>,
+
+    /// GID mapping for user namespace (container_id:host_id:size)
+    #[clap(long, multiple_occurrences(true), number_of_values = 1)]
+    gidmap: Vec<IdMap>,
+
+    #[clap(name = "IMAGE", parse(from_str = parse_image_id))]
+    image_id: ImageId,
+
+    command: Vec<String>,
+}
+
+impl Create {
+    pub fn exec(self) -> Result<()> {
+        let (state, _) = self.create()?;
+        println!("{}", state.id);
+
+        Ok(())
+    }
+
+    /// Saves state and start configuration of a new container.
+    pub(crate) fn create(mut self) -> Result<(State, Spec)> {
+        let curr_dir = std::env::current_dir()?;
+        let base_path = Path::new(&curr_dir).join(&self.image_id.name);
+
+        if !base_path.exists() {
+            let pull = Pull {
+                image_id: self.image_id.clone(),
+            };
+
+            pull.exec()?;
+        }
+
+        if !base_path.is_dir() {
+            bail!("Image directory not found");
+        }
+
+        let mut state = State::new(
+            self.name,
+            format!("{}:{}", self.image_id.name, self.image_id.tag),
+            self.hostname.clone(),
+        )?;
+
+        let image = Image::new(self.image_id.name, self.image_id.tag, base_path)?;
+
+        self.volumes.append(&mut self.tmpfs);
+        self.volumes.append(&mut self.mount);
+
+        // /tmp is always mounted as tmpfs
+        if self.read_only && self.read_only_tmpfs {
+            for destination in READ_ONLY_TMPFS_PATHS.iter().map(PathBuf::from) {
+                if !self
+                    .volumes
+                    .iter()
+                    .any(|volume| volume.destination == destination)
+                {
+                    self.volumes.push(Volume::tmpfs(destination));
+                }
+            }
+        }
+
+        let mut image_volumes = vec![];
+        if let Some(config) = image.configuration.config() {
+            // user volumes override the ones declared by the image
+            if let Some(volumes) = config.volumes() {
+                let user_volumes = &self.volumes;
+                image_volumes = volumes
+                    .iter()
+                    .map(PathBuf::from)
+                    .filter(|destination| {
+                        !user_volumes
+                            .iter()
+                            .any(|volume| &volume.destination == destination)
+                    })
+                    .collect();
+            }
+
+            if let Some(exposed_ports) = config.exposed_ports() {
+                if self.publish_all {
+                    let exposed_mappings = exposed_ports
+                        .iter()
+                        .map(|port| -> Result<PortMapping> {
+                            match PortMapping::exposed(port) {
+                                Ok(mapping) => Ok(mapping),
+                                Err(err) => Err(anyhow!(err)),
+                            }
+                        })
+                        .collect::<Result<Vec<PortMapping>>>()?;
+
+                    self.publish.extend(exposed_mappings);
+                }
+            }
+
+            if self.user.is_none() {
+                self.user = config.user().clone().filter(|user| !user.is_empty());
+            }
+
+            if self.workdir.is_none() {
+                self.workdir = config
+                    .working_dir()
+                    .clone()
+                    .filter(|workdir| !workdir.is_empty())
+                    .map(PathBuf::from);
+            }
+
+            if let Some(env) = config.env() {
+                let config_vars = env
+                    .iter()
+                    .map(|var| -> Result<EnvVariable> {
+                        match EnvVariable::from_str(var) {
+                            Ok(var) => Ok(var),
+                            Err(err) => Err(anyhow!(err)),
+                        }
+                    })
+                    .collect::<Result<Vec<EnvVariable>>>()?;
+
+                self.env.extend(config_vars);
+            }
+        }
+
+        let storage_driver = match self.storage_driver {
+            Some(storage_driver) => storage_driver,
+            None => StorageDriver::detect()?,
+        };
+        let mut storage_size = None;
+        for opt in self.storage_opt {
+            match opt {
+                StorageOpt::Size(size) => storage_size = Some(size),
+            }
+        }
+        if storage_size.is_some() && storage_driver == StorageDriver::Vfs {
+            bail!("Storage size limit is not supported by vfs storage driver");
+        }
+
+        if !self.publish.is_empty()
+            && matches!(self.network, NetworkMode::Host | NetworkMode::Container(_))
+        {
+            bail!("Ports can not be published in host or container network mode");
+        }
+
+        // kernel idmaps mounts of host file system only for a user privileged
+        // in the initial user namespace, rootless containers already see the
+        // files of their user owned by container's root
+        if !getuid().is_root() && self.volumes.iter().any(|volume| volume.options.idmap) {
+            bail!(
+                "Idmapped bind mounts require root, remove the idmap option \
+                 (files owned by you are owned by root in the container) \
+                 or run the container as root"
+            );
+        }
+
+        let mut volumes = self.volumes;
+        volumes.extend(image_volumes.into_iter().map(Volume::anonymous));
+        for volume in volumes.iter_mut() {
+            volume.resolve()?;
+        }
+
+        state.domainname = self.domainname.unwrap_or_default();
+        state.extra_hosts = self.add_host;
+        state.volumes = volumes
+            .iter()
+            .filter_map(|volume| volume.name().map(String::from))
+            .collect();
+        state.storage_driver = Some(storage_driver);
+        state.storage_size = storage_size;
+        state.save()?;
+
+        // files can be copied into the container before it is started
+        Bundle::new(image.clone(), state.dir()?, storage_driver, storage_size)?.prepare()?;
+
+        let spec = Spec {
+            image: ImageId {
+                name: image.name.clone(),
+                tag: image.tag.clone(),
+            },
+            image_path: image.base_path.clone(),
+            command: self.command,
+            env: self.env,
+            user: self.user.unwrap_or_else(|| String::from("0")),
+            group_add: self.group_add,
+            workdir: self.workdir.unwrap_or_else(|| PathBuf::from("/")),
+            volumes,
+            devices: self.device,
+            shm_size: self.shm_size,
+            cgroups: self.cgroups_config,
+            security: security::Config::new(self.security_opt.iter()),
+            uid_mappings: self.uidmap,
+            gid_mappings: self.gidmap,
+            network: self.network,
+            subnet: self.subnet,
+            publish: self.publish,
+            dns: self.dns,
+            dns_search: self.dns_search,
+            read_only: self.read_only,
+            auto_remove: self.rm,
+        };
+        spec.save(&state)?;
+
+        Ok((state, spec))
+    }
+}
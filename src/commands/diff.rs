@@ -0,0 +1,55 @@
+use anyhow::{bail, Result};
+use clap::Parser;
+use serde_json::json;
+
+use crate::{
+    container::{changes::changes, spec::Spec, state::State},
+    image::Image,
+};
+
+/// Inspect changes to files or directories on a container's file system
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Diff {
+    /// Print changes in JSON format
+    #[clap(long)]
+    json: bool,
+
+    /// Container name or id
+    #[clap(name = "CONTAINER")]
+    container: String,
+}
+
+impl Diff {
+    pub fn exec(self) -> Result<()> {
+        let state = State::find(&self.container)?;
+        let upperdir = match state.upperdir()? {
+            Some(upperdir) => upperdir,
+            None => bail!("Changes can be listed only with overlay storage drivers"),
+        };
+
+        let spec = Spec::load(&state)?;
+        let image = Image::new(spec.image.name, spec.image.tag, spec.image_path)?;
+        let changes = changes(&upperdir, &image.layer_paths())?;
+
+        if self.json {
+            let output = changes
+                .iter()
+                .map(|change| {
+                    json!({
+                        "Path": change.path,
+                        "Kind": change.kind.to_string(),
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            println!("{}", serde_json::to_string_pretty(&output)?);
+        } else {
+            for change in changes {
+                println!("{} {}", change.kind, change.path.display());
+            }
+        }
+
+        Ok(())
+    }
+}
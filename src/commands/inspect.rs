@@ -0,0 +1,52 @@
+use anyhow::Result;
+use clap::Parser;
+use serde_json::json;
+
+use crate::{container::state::State, util::dir_size};
+
+/// Display detailed information on containers
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Inspect {
+    #[clap(name = "CONTAINER", required = true)]
+    containers: Vec<String>,
+}
+
+impl Inspect {
+    pub fn exec(self) -> Result<()> {
+        let mut output = vec![];
+        for name_or_id in &self.containers {
+            let state = State::find(name_or_id)?;
+            let running = state.is_running();
+
+            output.push(json!({
+                "Id": state.id,
+                "Name": state.name,
+                "Image": state.image,
+                "Hostname": state.hostname,
+                "Domainname": state.domainname,
+                "Running": running,
+                "Pid": state.pid.filter(|_| running),
+                "Ports": state.ports.iter().map(|mapping| mapping.to_string()).collect::<Vec<_>>(),
+                "Volumes": state.volumes,
+                "Storage": {
+                    "Driver": state.storage_driver.map(|driver| driver.to_string()),
+                    "Size": state.storage_size,
+                    "Usage": writable_layer_usage(&state)?,
+                },
+            }));
+        }
+
+        println!("{}", serde_json::to_string_pretty(&output)?);
+
+        Ok(())
+    }
+}
+
+/// Disk usage of container's writable layer, if the storage driver has one.
+fn writable_layer_usage(state: &State) -> Result<Option<u64>> {
+    match state.upperdir()? {
+        Some(upperdir) if upperdir.exists() => Ok(Some(dir_size(&upperdir)?)),
+        _ => Ok(None),
+    }
+}
@@ -1,2 +1,12 @@
+pub mod commit;
+pub mod cp;
+pub mod create;
+pub mod diff;
+pub mod inspect;
+pub mod network;
+pub mod port;
 pub mod pull;
+pub mod rm;
 pub mod run;
+pub mod start;
+pub mod volume;
@@ -0,0 +1,112 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use serde_json::json;
+
+use crate::container::{
+    bridge,
+    ipam::{self, Subnet},
+};
+
+/// Manage networks
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Network {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Create(Create),
+    Ls(Ls),
+    Rm(Rm),
+    Inspect(Inspect),
+}
+
+/// Create a network
+#[derive(Parser, Debug)]
+struct Create {
+    /// Subnet in CIDR format (e.g. 10.89.0.0/24)
+    #[clap(long)]
+    subnet: Option<Subnet>,
+
+    #[clap(name = "NETWORK")]
+    name: String,
+}
+
+/// List networks
+#[derive(Parser, Debug)]
+struct Ls {}
+
+/// Remove networks
+#[derive(Parser, Debug)]
+struct Rm {
+    #[clap(name = "NETWORK", required = true)]
+    names: Vec<String>,
+}
+
+/// Display detailed information on networks
+#[derive(Parser, Debug)]
+struct Inspect {
+    #[clap(name = "NETWORK", required = true)]
+    names: Vec<String>,
+}
+
+impl Network {
+    pub fn exec(self) -> Result<()> {
+        match self.command {
+            Command::Create(create) => {
+                let network = ipam::Network::create(&create.name, create.subnet)?;
+                println!("{}", network.name);
+            }
+            Command::Ls(_) => {
+                println!("{:<20} {:<10} {:<18}", "NAME", "BRIDGE", "SUBNET");
+                for network in ipam::Network::list()? {
+                    println!(
+                        "{:<20} {:<10} {:<18}",
+                        network.name,
+                        network.bridge,
+                        network.subnet.to_string()
+                    );
+                }
+            }
+            Command::Rm(rm) => {
+                for name in rm.names {
+                    let network = ipam::Network::load(&name)?;
+                    network.remove()?;
+                    bridge::teardown(&network)?;
+                    println!("{}", network.name);
+                }
+            }
+            Command::Inspect(inspect) => {
+                let mut output = vec![];
+                for name in inspect.names {
+                    let network = ipam::Network::load(&name)?;
+                    let containers = network
+                        .containers()?
+                        .into_iter()
+                        .map(|(address, state)| {
+                            json!({
+                                "Id": state.id,
+                                "Name": state.name,
+                                "Address": address.to_string(),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+
+                    output.push(json!({
+                        "Name": network.name,
+                        "Bridge": network.bridge,
+                        "Subnet": network.subnet.to_string(),
+                        "Gateway": network.gateway.to_string(),
+                        "Containers": containers,
+                    }));
+                }
+
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use anyhow::Result;
+use clap::Parser;
+
+use crate::container::state::State;
+
+/// List port mappings of the container
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Port {
+    /// Container name or id
+    #[clap(name = "CONTAINER")]
+    container: String,
+}
+
+impl Port {
+    pub fn exec(self) -> Result<()> {
+        let state = State::find(&self.container)?;
+
+        for mapping in &state.ports {
+            println!("{}", mapping);
+        }
+
+        Ok(())
+    }
+}
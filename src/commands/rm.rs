@@ -0,0 +1,78 @@
+use std::{thread, time::Duration};
+
+use anyhow::Result;
+use clap::Parser;
+use nix::{
+    sys::signal::{kill, Signal},
+    unistd::Pid,
+};
+
+use crate::container::state::State;
+
+/// How long to wait for a killed container to be cleaned up by its con process
+const STOP_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Remove one or more containers
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Rm {
+    /// Kill and remove running containers
+    #[clap(short, long)]
+    force: bool,
+
+    /// Remove anonymous volumes associated with the container
+    #[clap(short, long)]
+    volumes: bool,
+
+    #[clap(name = "CONTAINER", required = true)]
+    containers: Vec<String>,
+}
+
+impl Rm {
+    pub fn exec(self) -> Result<()> {
+        for name_or_id in &self.containers {
+            let mut state = State::find(name_or_id)?;
+            if self.force && state.is_running() {
+                state = match stop(state)? {
+                    Some(state) => state,
+                    // container was started with --rm
+                    None => {
+                        println!("{}", name_or_id);
+                        continue;
+                    }
+                };
+            }
+
+            state.remove(self.volumes)?;
+            println!("{}", state.name);
+        }
+
+        Ok(())
+    }
+}
+
+/// Kills the container and waits until its con process releases its
+/// resources. Returns updated state or `None` if the container removed itself.
+fn stop(state: State) -> Result<Option<State>> {
+    if let Some(pid) = state.pid {
+        kill(Pid::from_raw(pid), Signal::SIGKILL)?;
+    }
+
+    let mut waited = Duration::ZERO;
+    while waited < STOP_TIMEOUT {
+        let state = match State::find(&state.id) {
+            Ok(state) => state,
+            Err(_) => return Ok(None),
+        };
+
+        if state.pid.is_none() {
+            return Ok(Some(state));
+        }
+
+        thread::sleep(POLL_INTERVAL);
+        waited += POLL_INTERVAL;
+    }
+
+    Ok(Some(State::find(&state.id)?))
+}
@@ -0,0 +1,434 @@
+use std::{
+    ffi::CString,
+    fs::{create_dir_all, File, OpenOptions},
+    net::IpAddr,
+    os::unix::io::AsRawFd,
+    path::Path,
+    process, thread,
+    time::Duration,
+};
+
+use anyhow::{bail, Result};
+use clap::Parser;
+use nix::{
+    sched::{clone, CloneFlags},
+    sys::wait::{waitpid, WaitPidFlag, WaitStatus},
+    unistd::{self, chdir, chown, dup2, execve, fork, getuid, setsid, ForkResult, Gid, Pid, Uid},
+};
+
+use crate::{
+    container::{
+        bridge,
+        bundle::Bundle,
+        capabilities,
+        cgroups::CGroup,
+        devices, hosts,
+        ipam::{self, Lease, Network},
+        namespaces::{self, NetworkNamespace},
+        network::{Connection, NetworkMode, Veth},
+        ports,
+        proxy::Proxy,
+        resolv::{self, ResolvConf},
+        security, slirp,
+        spec::Spec,
+        state::State,
+        storage::StorageDriver,
+        user,
+    },
+    image::Image,
+};
+
+/// Start a created or stopped container
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Start {
+    /// Attach to container's output and wait until it exits, otherwise it
+    /// runs in background with output written to its log file
+    #[clap(short, long)]
+    attach: bool,
+
+    /// Container name or id
+    #[clap(name = "CONTAINER")]
+    container: String,
+}
+
+impl Start {
+    pub fn exec(self) -> Result<()> {
+        let state = State::find(&self.container)?;
+        if state.is_running() {
+            bail!("Container {} is already running", state.name);
+        }
+
+        let spec = Spec::load(&state)?;
+
+        if self.attach {
+            start(state, spec)
+        } else {
+            start_detached(state, spec)
+        }
+    }
+}
+
+/// Starts the container in a background process and returns once it is
+/// running.
+fn start_detached(mut state: State, spec: Spec) -> Result<()> {
+    // pid left by a crashed run would be taken for the started container
+    state.pid = None;
+    state.save()?;
+
+    let log_path = state.dir()?.join("container.log");
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)?;
+    let null = File::open("/dev/null")?;
+    let id = state.id.clone();
+    let name = state.name.clone();
+
+    match unsafe { fork()? } {
+        ForkResult::Child => {
+            setsid().expect("Failed creating session");
+            dup2(null.as_raw_fd(), 0).expect("Failed redirecting standard input");
+            dup2(log.as_raw_fd(), 1).expect("Failed redirecting standard output");
+            dup2(log.as_raw_fd(), 2).expect("Failed redirecting standard error");
+
+            let code = match start(state, spec) {
+                Ok(_) => 0,
+                Err(err) => {
+                    eprintln!("Error: {:?}", err);
+                    1
+                }
+            };
+            process::exit(code);
+        }
+        ForkResult::Parent { child } => loop {
+            match waitpid(child, Some(WaitPidFlag::WNOHANG))? {
+                WaitStatus::Exited(_, 0) => break,
+                WaitStatus::StillAlive => {}
+                _ => bail!("Container failed to start, see {}", log_path.display()),
+            }
+
+            // container started with --rm could have already removed itself,
+            // container whose process already exited is checked by its exit
+            // status
+            match State::find(&id) {
+                Ok(state) if !state.is_running() => {}
+                _ => break,
+            }
+
+            thread::sleep(Duration::from_millis(50));
+        },
+    }
+
+    println!("{}", name);
+
+    Ok(())
+}
+
+/// Runs container described by `state` and `spec` until its process exits.
+pub(crate) fn start(mut state: State, spec: Spec) -> Result<()> {
+    let auto_remove = spec.auto_remove;
+    let result = run_container(&mut state, spec);
+
+    // container is stopped (or removed) also when it failed to start
+    state.pid = None;
+    if auto_remove {
+        state.remove(true)?;
+    } else {
+        state.save()?;
+    }
+
+    result
+}
+
+fn run_container(state: &mut State, mut spec: Spec) -> Result<()> {
+    let image = Image::new(
+        spec.image.name.clone(),
+        spec.image.tag.clone(),
+        spec.image_path.clone(),
+    )?;
+
+    // volumes are populated only when they are mounted for the first time
+    let volumes = spec.volumes.clone();
+    for volume in spec.volumes.iter_mut() {
+        volume.copy_up = false;
+    }
+    spec.save(state)?;
+
+    let storage_driver = state.storage_driver.unwrap_or(StorageDriver::Overlay);
+    let storage_size = state.storage_size;
+
+    let mut device_rules = devices::default_rules();
+    for device in &spec.devices {
+        device_rules.push(device.rule()?);
+    }
+
+    // binding reserves the host ports and picks the free ones
+    let mut port_mappings = spec.publish.clone();
+    let mut port_listeners = Some(ports::bind(&mut port_mappings)?);
+    state.ports = port_mappings.clone();
+    state.save()?;
+
+    let mut resolv_conf = match spec.network {
+        NetworkMode::Private { .. } => ResolvConf {
+            nameservers: vec![IpAddr::V4(slirp::DNS_ADDRESS)],
+            ..ResolvConf::host()?
+        },
+        NetworkMode::None | NetworkMode::Bridge(_) => ResolvConf::host()?.without_loopback(),
+        NetworkMode::Host | NetworkMode::Container(_) => ResolvConf::host()?,
+    };
+    if !spec.dns.is_empty() {
+        resolv_conf.nameservers = spec.dns.clone();
+    }
+    if !spec.dns_search.is_empty() {
+        resolv_conf.search = spec.dns_search.clone();
+    }
+    resolv_conf.write(state)?;
+    hosts::write_hostname(state)?;
+
+    let mut bridge_lease = None;
+    let network_namespace = match &spec.network {
+        NetworkMode::None => {
+            hosts::write_hosts(state, IpAddr::V4(hosts::LOOPBACK_ADDRESS), &[])?;
+            NetworkNamespace::New(Connection::None)
+        }
+        NetworkMode::Private { host_loopback } => {
+            hosts::write_hosts(state, IpAddr::V4(slirp::CONTAINER_ADDRESS), &[])?;
+            NetworkNamespace::New(Connection::Userspace {
+                host_loopback: *host_loopback,
+            })
+        }
+        NetworkMode::Bridge(name) => {
+            if !getuid().is_root() {
+                bail!("Bridge network requires root, use private network instead");
+            }
+
+            let network = if name == ipam::DEFAULT_NETWORK {
+                Network::default_bridge(spec.subnet)?
+            } else if spec.subnet.is_some() {
+                bail!(
+                    "Subnet of network {} is set when the network is created",
+                    name
+                );
+            } else {
+                Network::load(name)?
+            };
+
+            // lease is released on errors when it is dropped
+            let lease = network.allocate(&state.id)?;
+            bridge::setup(&network)?;
+            hosts::refresh(&network)?;
+
+            // ports are forwarded by DNAT rules instead of the proxy, the
+            // listeners keep them reserved until the rules are installed
+            port_listeners = None;
+
+            let veth = Veth::new(
+                &state.id,
+                network.bridge.clone(),
+                lease.address,
+                network.subnet.prefix_len,
+                network.gateway,
+            );
+            bridge_lease = Some(lease);
+
+            NetworkNamespace::New(Connection::Veth(veth))
+        }
+        NetworkMode::Host => {
+            hosts::write_hosts(state, IpAddr::V4(hosts::LOOPBACK_ADDRESS), &[])?;
+            NetworkNamespace::Host
+        }
+        NetworkMode::Container(name_or_id) => {
+            hosts::write_hosts(state, IpAddr::V4(hosts::LOOPBACK_ADDRESS), &[])?;
+
+            let other = State::find(name_or_id)?;
+            match other.pid {
+                Some(pid) if other.is_running() => NetworkNamespace::Join(Pid::from_raw(pid)),
+                _ => bail!("Container {} is not running", other.name),
+            }
+        }
+    };
+
+    let container_dir = state.dir()?;
+    let generated_files = [
+        (hosts::hosts_path(state)?, "/etc/hosts"),
+        (hosts::hostname_path(state)?, "/etc/hostname"),
+        (resolv::resolv_path(state)?, "/etc/resolv.conf"),
+    ];
+    let mut proxy = None;
+
+    let namespaces_config = namespaces::Config::new(
+        spec.uid_mappings.clone(),
+        spec.gid_mappings.clone(),
+        network_namespace,
+    )?;
+
+    let hostname = state.hostname.clone();
+    let domainname = state.domainname.clone();
+
+    let cgroup = CGroup::new(&state.id, &spec.cgroups)?;
+    let result = namespaces::run(
+        &namespaces_config,
+        || {
+            let bundle = Bundle::new(
+                image.clone(),
+                container_dir.clone(),
+                storage_driver,
+                storage_size,
+            )?;
+
+            bundle.mount_rootfs()?;
+            bundle.mount_dev(spec.shm_size, spec.devices.iter())?;
+            bundle.mount_volumes(volumes.iter())?;
+            for (source, container_path) in &generated_files {
+                bundle.mount_file(source, container_path)?;
+            }
+            bundle.mount_special(&spec.security)?;
+
+            unistd::sethostname(&hostname)?;
+            if !domainname.is_empty() {
+                hosts::set_domainname(&domainname)?;
+            }
+
+            capabilities::run()?;
+
+            let child = Box::new(|| {
+                bundle
+                    .change_root()
+                    .expect("Failed setting container root file system");
+
+                let user = user::resolve(Path::new("/"), &spec.user, &spec.group_add)
+                    .expect("Failed resolving container user");
+
+                let mut env_vars = spec
+                    .env
+                    .iter()
+                    .map(|e| CString::new(format!("{}={}", e.key, e.value)).unwrap())
+                    .collect::<Vec<_>>();
+
+                if !spec.env.iter().any(|e| e.key == "HOME") {
+                    env_vars.push(
+                        CString::new(format!("HOME={}", user.home.to_str().unwrap())).unwrap(),
+                    );
+                }
+
+                // user owns every directory created for the working directory
+                let workdir = &spec.workdir;
+                let created = workdir
+                    .ancestors()
+                    .take_while(|dir| !dir.exists())
+                    .collect::<Vec<_>>();
+                create_dir_all(workdir).expect("Failed creating working directory");
+                for dir in created {
+                    chown(
+                        dir,
+                        Some(Uid::from_raw(user.uid)),
+                        Some(Gid::from_raw(user.gid)),
+                    )
+                    .expect("Failed changing working directory owner");
+                }
+
+                if spec.read_only {
+                    bundle
+                        .remount_root_readonly()
+                        .expect("Failed remounting root file system read-only");
+                }
+
+                user::switch(&user).expect("Failed switching to container user");
+                chdir(workdir).expect("Failed changing working directory");
+
+                if spec.security.no_new_privileges {
+                    security::set_no_new_privs().expect("Failed setting no_new_privs");
+                }
+
+                // program name is passed as the first argument too
+                let command = &spec.command;
+                execve(
+                    CString::new(command[0].clone()).unwrap().as_c_str(),
+                    command
+                        .iter()
+                        .map(|c| CString::new(c.to_owned()).unwrap().as_c_str().to_owned())
+                        .collect::<Vec<_>>()
+                        .as_slice(),
+                    env_vars.as_slice(),
+                )
+                .expect("Error executing command");
+
+                0
+            });
+
+            let child_pid = clone(
+                child,
+                &mut [0u8; 1024 * 1024],
+                CloneFlags::CLONE_NEWNS,
+                None,
+            )?;
+            let status = waitpid(child_pid, Some(WaitPidFlag::__WALL))?;
+
+            bundle.unmount_special()?;
+            for (_, container_path) in &generated_files {
+                bundle.unmount_file(container_path)?;
+            }
+            bundle.unmount_volumes(volumes.iter())?;
+            bundle.unmount_dev()?;
+            bundle.unmount_rootfs()?;
+
+            Ok(namespaces::exit_code(status))
+        },
+        |child_pid| {
+            state.pid = Some(child_pid.as_raw());
+            state.save()?;
+
+            cgroup.add_process(child_pid.as_raw() as u64)?;
+
+            // device access can be restricted only by privileged user,
+            // rootless container can use only device nodes bind mounted into
+            // its /dev since it can not create usable ones
+            if getuid().is_root() {
+                cgroup.allow_devices(&device_rules)?;
+            }
+
+            if let Some(listeners) = port_listeners.take() {
+                if !listeners.is_empty() {
+                    proxy = Some(Proxy::spawn(child_pid, port_mappings, listeners)?);
+                }
+            }
+
+            Ok(())
+        },
+    );
+
+    // resources are released also when the container failed to start
+    let cleanup = release_resources(cgroup, proxy, bridge_lease);
+
+    match result? {
+        WaitStatus::Exited(_, 0) => {}
+        WaitStatus::Signaled(_, signal, _) => bail!("Container was killed by {}", signal),
+        status => bail!(
+            "Container exited with code {}",
+            namespaces::exit_code(status)
+        ),
+    }
+
+    cleanup
+}
+
+/// Releases every resource even if releasing of some fails, returns the first
+/// error.
+fn release_resources(
+    mut cgroup: CGroup,
+    proxy: Option<Proxy>,
+    bridge_lease: Option<Lease>,
+) -> Result<()> {
+    let results = vec![
+        cgroup.delete(),
+        proxy.map_or(Ok(()), Proxy::stop),
+        bridge_lease.map_or(Ok(()), |mut lease| {
+            lease.release()?;
+            hosts::refresh(&lease.network)?;
+            bridge::apply_nat_rules()
+        }),
+    ];
+
+    results.into_iter().collect()
+}
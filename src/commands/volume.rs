@@ -0,0 +1,116 @@
+use anyhow::Result;
+use clap::{Parser, Subcommand};
+use serde_json::json;
+
+use crate::{util::generate_id, volume::NamedVolume};
+
+/// Manage volumes
+#[derive(Parser, Debug)]
+#[clap(author, version)]
+pub struct Volume {
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    Create(Create),
+    Ls(Ls),
+    Rm(Rm),
+    Inspect(Inspect),
+    Prune(Prune),
+}
+
+/// Create a volume
+#[derive(Parser, Debug)]
+struct Create {
+    /// Set metadata for a volume (key=value)
+    #[clap(long, multiple_occurrences(true), number_of_values = 1, parse(try_from_str = parse_label))]
+    label: Vec<(String, String)>,
+
+    /// Volume name, generated if not given
+    #[clap(name = "VOLUME")]
+    name: Option<String>,
+}
+
+/// List volumes
+#[derive(Parser, Debug)]
+struct Ls {}
+
+/// Remove volumes
+#[derive(Parser, Debug)]
+struct Rm {
+    #[clap(name = "VOLUME", required = true)]
+    names: Vec<String>,
+}
+
+/// Display detailed information on volumes
+#[derive(Parser, Debug)]
+struct Inspect {
+    #[clap(name = "VOLUME", required = true)]
+    names: Vec<String>,
+}
+
+/// Remove all volumes not used by any container
+#[derive(Parser, Debug)]
+struct Prune {}
+
+fn parse_label(s: &str) -> Result<(String, String), &'static str> {
+    match s.split_once("=") {
+        Some((key, value)) => Ok((key.to_string(), value.to_string())),
+        None => Ok((s.to_string(), String::new())),
+    }
+}
+
+impl Volume {
+    pub fn exec(self) -> Result<()> {
+        match self.command {
+            Command::Create(create) => {
+                let name = match create.name {
+                    Some(name) => name,
+                    None => generate_id()?,
+                };
+
+                let volume = NamedVolume::create(&name, create.label.into_iter().collect())?;
+                println!("{}", volume.name);
+            }
+            Command::Ls(_) => {
+                println!("VOLUME NAME");
+                for volume in NamedVolume::list()? {
+                    println!("{}", volume.name);
+                }
+            }
+            Command::Rm(rm) => {
+                for name in rm.names {
+                    let volume = NamedVolume::load(&name)?;
+                    volume.remove()?;
+                    println!("{}", volume.name);
+                }
+            }
+            Command::Inspect(inspect) => {
+                let mut output = vec![];
+                for name in inspect.names {
+                    let volume = NamedVolume::load(&name)?;
+
+                    output.push(json!({
+                        "Name": volume.name,
+                        "Mountpoint": volume.data_path()?,
+                        "Labels": volume.labels,
+                    }));
+                }
+
+                println!("{}", serde_json::to_string_pretty(&output)?);
+            }
+            Command::Prune(_) => {
+                for volume in NamedVolume::list()? {
+                    if volume.containers()?.is_empty() {
+                        volume.remove()?;
+                        println!("{}", volume.name);
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use std::{mem::size_of, path::Path};
+
+use anyhow::Result;
+use nix::{
+    errno::Errno,
+    fcntl::{open, OFlag},
+    libc,
+    sys::stat::Mode,
+    unistd::close,
+};
+
+use crate::container::devices::{DeviceKind, DeviceRule};
+
+const BPF_PROG_LOAD: libc::c_long = 5;
+const BPF_PROG_ATTACH: libc::c_long = 8;
+const BPF_PROG_TYPE_CGROUP_DEVICE: u32 = 15;
+const BPF_CGROUP_DEVICE: u32 = 6;
+
+const BPF_DEVCG_ACC_MKNOD: i32 = 1;
+const BPF_DEVCG_ACC_READ: i32 = 2;
+const BPF_DEVCG_ACC_WRITE: i32 = 4;
+const BPF_DEVCG_DEV_BLOCK: i32 = 1;
+const BPF_DEVCG_DEV_CHAR: i32 = 2;
+
+// Opcodes used by the device filter program
+const LDX_MEM_W: u8 = 0x61;
+const ALU32_AND_K: u8 = 0x54;
+const ALU32_RSH_K: u8 = 0x74;
+const ALU32_MOV_X: u8 = 0xbc;
+const ALU64_MOV_K: u8 = 0xb7;
+const JMP_JNE_K: u8 = 0x55;
+const JMP_JNE_X: u8 = 0x5d;
+const JMP_EXIT: u8 = 0x95;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct Insn {
+    code: u8,
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+fn insn(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> Insn {
+    Insn {
+        code,
+        regs: (src << 4) | dst,
+        off,
+        imm,
+    }
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct ProgAttachAttr {
+    target_fd: u32,
+    attach_bpf_fd: u32,
+    attach_type: u32,
+    attach_flags: u32,
+}
+
+fn access_mask(permissions: &str) -> i32 {
+    permissions.chars().fold(0, |mask, c| match c {
+        'r' => mask | BPF_DEVCG_ACC_READ,
+        'w' => mask | BPF_DEVCG_ACC_WRITE,
+        'm' => mask | BPF_DEVCG_ACC_MKNOD,
+        _ => mask,
+    })
+}
+
+/// Compiles rules into `BPF_PROG_TYPE_CGROUP_DEVICE` program. Rules are
+/// checked in order and access to devices not matching any rule is denied.
+///
+/// Registers: r2 - device type, r3 - access type, r4 - major, r5 - minor.
+fn program(rules: &[DeviceRule]) -> Vec<Insn> {
+    let mut insns = vec![
+        insn(LDX_MEM_W, 2, 1, 0, 0),
+        insn(ALU32_AND_K, 2, 0, 0, 0xffff),
+        insn(LDX_MEM_W, 3, 1, 0, 0),
+        insn(ALU32_RSH_K, 3, 0, 0, 16),
+        insn(LDX_MEM_W, 4, 1, 4, 0),
+        insn(LDX_MEM_W, 5, 1, 8, 0),
+    ];
+
+    for rule in rules {
+        let mut block = vec![];
+
+        match rule.kind {
+            DeviceKind::All => {}
+            DeviceKind::Char => block.push(insn(JMP_JNE_K, 2, 0, 0, BPF_DEVCG_DEV_CHAR)),
+            DeviceKind::Block => block.push(insn(JMP_JNE_K, 2, 0, 0, BPF_DEVCG_DEV_BLOCK)),
+        }
+
+        let mask = access_mask(&rule.permissions);
+        if mask != access_mask("rwm") {
+            // requested access has to be a subset of allowed access
+            block.push(insn(ALU32_MOV_X, 1, 3, 0, 0));
+            block.push(insn(ALU32_AND_K, 1, 0, 0, mask));
+            block.push(insn(JMP_JNE_X, 1, 3, 0, 0));
+        }
+
+        if let Some(major) = rule.major {
+            block.push(insn(JMP_JNE_K, 4, 0, 0, major as i32));
+        }
+
+        if let Some(minor) = rule.minor {
+            block.push(insn(JMP_JNE_K, 5, 0, 0, minor as i32));
+        }
+
+        block.push(insn(ALU64_MOV_K, 0, 0, 0, 1));
+        block.push(insn(JMP_EXIT, 0, 0, 0, 0));
+
+        // every jump in the block skips to the next rule
+        let len = block.len();
+        for (i, insn) in block.iter_mut().enumerate() {
+            if insn.code == JMP_JNE_K || insn.code == JMP_JNE_X {
+                insn.off = (len - i - 1) as i16;
+            }
+        }
+
+        insns.extend(block);
+    }
+
+    insns.push(insn(ALU64_MOV_K, 0, 0, 0, 0));
+    insns.push(insn(JMP_EXIT, 0, 0, 0, 0));
+
+    insns
+}
+
+fn bpf<T>(cmd: libc::c_long, attr: &T) -> Result<i32> {
+    let res = unsafe { libc::syscall(libc::SYS_bpf, cmd, attr as *const T, size_of::<T>()) };
+
+    Ok(Errno::result(res)? as i32)
+}
+
+/// Attaches device filter allowing only devices matching `rules` to cgroup v2
+/// at `cgroup_path`.
+pub fn attach_device_filter(cgroup_path: &Path, rules: &[DeviceRule]) -> Result<()> {
+    let insns = program(rules);
+    let license = b"GPL\0";
+
+    let load_attr = ProgLoadAttr {
+        prog_type: BPF_PROG_TYPE_CGROUP_DEVICE,
+        insn_cnt: insns.len() as u32,
+        insns: insns.as_ptr() as u64,
+        license: license.as_ptr() as u64,
+        ..Default::default()
+    };
+    let prog_fd = bpf(BPF_PROG_LOAD, &load_attr)?;
+
+    let cgroup_fd = open(
+        cgroup_path,
+        OFlag::O_RDONLY | OFlag::O_DIRECTORY,
+        Mode::empty(),
+    )?;
+
+    let attach_attr = ProgAttachAttr {
+        target_fd: cgroup_fd as u32,
+        attach_bpf_fd: prog_fd as u32,
+        attach_type: BPF_CGROUP_DEVICE,
+        ..Default::default()
+    };
+    let res = bpf(BPF_PROG_ATTACH, &attach_attr);
+
+    close(cgroup_fd)?;
+    close(prog_fd)?;
+
+    res?;
+
+    Ok(())
+}
@@ -0,0 +1,197 @@
+use std::{
+    fs::write,
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use anyhow::{bail, Result};
+use nix::unistd::Pid;
+
+use crate::container::{
+    ipam::Network,
+    network::{block_on, connect, link_index, Veth},
+    state::State,
+};
+
+const NAT_TABLE: &str = "con";
+
+/// Creates bridge of `network` if it does not exist yet, enables forwarding
+/// and refreshes NAT rules. Requires root.
+pub fn setup(network: &Network) -> Result<()> {
+    block_on(async {
+        let handle = connect().await?;
+
+        let index = match link_index(&handle, &network.bridge).await {
+            Ok(index) => index,
+            Err(_) => {
+                handle
+                    .link()
+                    .add()
+                    .bridge(network.bridge.clone())
+                    .execute()
+                    .await?;
+
+                let index = link_index(&handle, &network.bridge).await?;
+                handle
+                    .address()
+                    .add(index, network.gateway.into(), network.subnet.prefix_len)
+                    .execute()
+                    .await?;
+
+                index
+            }
+        };
+
+        handle.link().set(index).up().execute().await?;
+
+        Ok(())
+    })?;
+
+    write("/proc/sys/net/ipv4/ip_forward", "1")?;
+    // published ports are reachable also through host's loopback
+    write(
+        format!("/proc/sys/net/ipv4/conf/{}/route_localnet", network.bridge),
+        "1",
+    )?;
+
+    apply_nat_rules()
+}
+
+/// Deletes bridge of removed `network` and refreshes NAT rules.
+pub fn teardown(network: &Network) -> Result<()> {
+    block_on(async {
+        let handle = connect().await?;
+
+        if let Ok(index) = link_index(&handle, &network.bridge).await {
+            handle.link().del(index).execute().await?;
+        }
+
+        Ok(())
+    })?;
+
+    apply_nat_rules()
+}
+
+/// Creates veth pair `veth`, attaches host end to the bridge and moves the
+/// peer into network namespace of process `pid`.
+pub fn attach(veth: &Veth, pid: &Pid) -> Result<()> {
+    block_on(async {
+        let handle = connect().await?;
+
+        handle
+            .link()
+            .add()
+            .veth(veth.host_name.clone(), veth.peer_name.clone())
+            .execute()
+            .await?;
+
+        let bridge_index = link_index(&handle, &veth.bridge).await?;
+        let host_index = link_index(&handle, &veth.host_name).await?;
+        let peer_index = link_index(&handle, &veth.peer_name).await?;
+
+        handle
+            .link()
+            .set(host_index)
+            .master(bridge_index)
+            .execute()
+            .await?;
+        handle.link().set(host_index).up().execute().await?;
+        handle
+            .link()
+            .set(peer_index)
+            .setns_by_pid(pid.as_raw() as u32)
+            .execute()
+            .await?;
+
+        Ok(())
+    })
+}
+
+/// Replaces con's nftables table with masquerading rules for all bridge
+/// networks and port forwarding rules for containers holding an address lease.
+pub fn apply_nat_rules() -> Result<()> {
+    // declaring the table first makes the deletion succeed when it does not
+    // exist yet
+    let mut script = format!(
+        "table ip {0} {{}}\ndelete table ip {0}\ntable ip {0} {{\n",
+        NAT_TABLE
+    );
+
+    let networks = Network::list()?;
+    let states = State::list()?;
+
+    let mut dnat = String::new();
+    for network in &networks {
+        for (address, id) in network.leases()? {
+            let ports = match states.iter().find(|state| state.id == id) {
+                Some(state) => &state.ports,
+                None => continue,
+            };
+
+            for mapping in ports {
+                dnat.push_str("fib daddr type local ");
+                if !mapping.host_ip.is_unspecified() {
+                    dnat.push_str(&format!("ip daddr {} ", mapping.host_ip));
+                }
+                dnat.push_str(&format!(
+                    "{} dport {} dnat to {}:{}\n",
+                    mapping.protocol, mapping.host_port, address, mapping.container_port
+                ));
+            }
+        }
+    }
+
+    script.push_str("chain prerouting {\n");
+    script.push_str("type nat hook prerouting priority dstnat; policy accept;\n");
+    script.push_str(&dnat);
+    script.push_str("}\n");
+
+    script.push_str("chain output {\n");
+    script.push_str("type nat hook output priority -100; policy accept;\n");
+    script.push_str(&dnat);
+    script.push_str("}\n");
+
+    script.push_str("chain postrouting {\n");
+    script.push_str("type nat hook postrouting priority srcnat; policy accept;\n");
+    for network in &networks {
+        script.push_str(&format!(
+            "ip saddr {0} ip daddr != {0} masquerade\n",
+            network.subnet
+        ));
+        script.push_str(&format!(
+            "oifname \"{}\" ip saddr 127.0.0.0/8 masquerade\n",
+            network.bridge
+        ));
+    }
+    script.push_str("}\n");
+
+    script.push_str("chain forward {\n");
+    script.push_str("type filter hook forward priority filter; policy accept;\n");
+    for network in &networks {
+        script.push_str(&format!("ip saddr {} accept\n", network.subnet));
+        script.push_str(&format!(
+            "ip daddr {} ct state established,related accept\n",
+            network.subnet
+        ));
+        script.push_str(&format!(
+            "ip daddr {} ct status dnat accept\n",
+            network.subnet
+        ));
+    }
+    script.push_str("}\n");
+
+    script.push_str("}\n");
+
+    let mut nft = Command::new("nft")
+        .args(["-f", "-"])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    nft.stdin.take().unwrap().write_all(script.as_bytes())?;
+
+    let status = nft.wait()?;
+    if !status.success() {
+        bail!("nft failed with {}", status);
+    }
+
+    Ok(())
+}
@@ -1,36 +1,61 @@
 use std::{
-    fs::{create_dir, remove_dir, remove_dir_all},
-    path::PathBuf,
+    ffi::OsString,
+    fs::{
+        create_dir, create_dir_all, read_to_string, remove_dir, remove_file, symlink_metadata, File,
+    },
+    os::unix::{ffi::OsStringExt, fs::symlink},
+    path::{Path, PathBuf},
 };
 
 use anyhow::Result;
 use nix::{
     mount::{mount, umount, umount2, MntFlags, MsFlags},
+    sys::statvfs::{statvfs, FsFlags},
     unistd::{chdir, pivot_root},
 };
 
-use crate::{image::Image, volume::Volume};
+use crate::{
+    container::{
+        devices::{self, Device},
+        idmapped, security,
+        storage::StorageDriver,
+    },
+    image::Image,
+    util::copy_dir,
+    volume::{Volume, VolumeKind},
+};
 
 pub struct Bundle {
     pub(crate) dir: PathBuf,
     pub(crate) image: Image,
+    pub(crate) driver: StorageDriver,
+    /// Size limit of the writable layer
+    pub(crate) storage_size: Option<u64>,
 }
 
 impl Bundle {
-    pub fn new(image: Image, dir: PathBuf) -> Result<Self> {
+    pub fn new(
+        image: Image,
+        dir: PathBuf,
+        driver: StorageDriver,
+        storage_size: Option<u64>,
+    ) -> Result<Self> {
+        // directories are kept when the container is stopped
         let root_path = Self::root_path_inner(&dir);
-        create_dir(&root_path)?;
-
-        let workdir_path = Self::workdir_path_inner(&dir);
-        create_dir(&workdir_path)?;
+        create_dir_all(&root_path)?;
 
-        let upperdir_path = Self::upperdir_path_inner(&dir);
-        create_dir(&upperdir_path)?;
+        let storage_path = Self::storage_path_inner(&dir);
+        create_dir_all(&storage_path)?;
 
-        Ok(Self { image, dir })
+        Ok(Self {
+            image,
+            dir,
+            driver,
+            storage_size,
+        })
     }
 
-    fn root_path_inner(dir: &PathBuf) -> PathBuf {
+    pub(crate) fn root_path_inner(dir: &PathBuf) -> PathBuf {
         dir.join("rootfs")
     }
 
@@ -38,16 +63,24 @@ impl Bundle {
         Self::root_path_inner(&self.dir)
     }
 
+    fn storage_path_inner(dir: &PathBuf) -> PathBuf {
+        dir.join("storage")
+    }
+
+    pub fn storage_path(&self) -> PathBuf {
+        Self::storage_path_inner(&self.dir)
+    }
+
     fn workdir_path_inner(dir: &PathBuf) -> PathBuf {
-        dir.join("workdir")
+        Self::storage_path_inner(dir).join("workdir")
     }
 
     pub fn workdir_path(&self) -> PathBuf {
         Self::workdir_path_inner(&self.dir)
     }
 
-    fn upperdir_path_inner(dir: &PathBuf) -> PathBuf {
-        dir.join("upperdir")
+    pub(crate) fn upperdir_path_inner(dir: &PathBuf) -> PathBuf {
+        Self::storage_path_inner(dir).join("upperdir")
     }
 
     pub fn upperdir_path(&self) -> PathBuf {
@@ -64,45 +97,72 @@ impl Bundle {
         Ok(self.root_path().join(&path))
     }
 
-    pub fn mount_overlayfs(&self) -> Result<()> {
-        let layer_paths: Vec<String> = self
-            .image
-            .layer_paths()
-            .iter()
-            .map(|p| p.to_str().unwrap().to_string())
-            .collect();
+    /// Marks root file system populated by vfs driver.
+    fn populated_marker_path(&self) -> PathBuf {
+        self.storage_path().join("populated")
+    }
+
+    /// Prepares storage of a created container, so that its files can be
+    /// copied before it is started.
+    pub fn prepare(&self) -> Result<()> {
+        create_dir_all(self.upperdir_path())?;
+        create_dir_all(self.workdir_path())?;
+
+        self.driver.populate(
+            &self.image.layer_paths(),
+            &self.root_path(),
+            &self.populated_marker_path(),
+        )
+    }
+
+    pub fn mount_rootfs(&self) -> Result<()> {
+        // writable layer is limited by keeping it on tmpfs of given size
+        if let Some(size) = self.storage_size {
+            mount(
+                Some("tmpfs"),
+                &self.storage_path(),
+                Some("tmpfs"),
+                MsFlags::MS_NOSUID | MsFlags::MS_NODEV,
+                Some(format!("mode=755,size={}", size).as_str()),
+            )?;
+        }
+
+        self.prepare()?;
+
+        self.driver.mount(
+            &self.image.layer_paths(),
+            &self.root_path(),
+            &self.upperdir_path(),
+            &self.workdir_path(),
+        )
+    }
+
+    /// Makes the root mount read only once all mount points and the working
+    /// directory have been created in it, called after `change_root`.
+    pub fn remount_root_readonly(&self) -> Result<()> {
+        let root_path = Path::new("/");
 
         mount(
             None::<&str>,
-            &self.root_path(),
-            Some("overlay"),
-            MsFlags::empty(),
-            Some(
-                format!(
-                    "lowerdir={},upperdir={},workdir={}",
-                    layer_paths.join(":"),
-                    &self.upperdir_path().to_str().unwrap(),
-                    &self.workdir_path().to_str().unwrap()
-                )
-                .as_str(),
-            ),
+            root_path,
+            None::<&str>,
+            MsFlags::MS_BIND | MsFlags::MS_REMOUNT | MsFlags::MS_RDONLY | locked_flags(root_path)?,
+            None::<&str>,
         )?;
 
         Ok(())
     }
 
-    pub fn unmount_overlayfs(&self) -> Result<()> {
-        umount(&self.root_path())?;
-        remove_dir_all(&self.dir)?;
+    pub fn unmount_rootfs(&self) -> Result<()> {
+        self.driver.unmount(&self.root_path())?;
+        if self.storage_size.is_some() {
+            umount(&self.storage_path())?;
+        }
 
         Ok(())
     }
 
     pub fn change_root(&self) -> Result<()> {
-        let root_path = self.root_path();
-        let old_root = root_path.join("old_root");
-        create_dir(&old_root)?;
-
         mount(
             None::<&str>,
             "/",
@@ -111,16 +171,17 @@ impl Bundle {
             None::<&str>,
         )?;
 
-        pivot_root(&root_path, &old_root)?;
+        // old root is stacked under the new one, so no directory for it has
+        // to be created in the root which can be read only
+        chdir(&self.root_path())?;
+        pivot_root(".", ".")?;
+        umount2(".", MntFlags::MNT_DETACH)?;
         chdir("/")?;
 
-        umount2("/old_root", MntFlags::MNT_DETACH)?;
-        remove_dir("/old_root")?;
-
         Ok(())
     }
 
-    pub fn mount_special(&self) -> Result<()> {
+    pub fn mount_special(&self, security: &security::Config) -> Result<()> {
         let root_path = self.root_path();
 
         let oldproc = root_path.join(".oldproc");
@@ -163,6 +224,10 @@ impl Bundle {
         )?;
 
         let sys = root_path.join("sys");
+        if !sys.exists() {
+            create_dir(&sys)?;
+        }
+
         mount(
             Some("/sys"),
             &sys,
@@ -171,35 +236,202 @@ impl Bundle {
             None::<&str>,
         )?;
 
+        for path in &security.readonly_paths {
+            let path = self.host_path_from_container_path(path)?;
+            if path.exists() {
+                mount_readonly(&path)?;
+            }
+        }
+
+        for path in &security.masked_paths {
+            let path = self.host_path_from_container_path(path)?;
+            if path.exists() {
+                mask(&path)?;
+            }
+        }
+
         Ok(())
     }
 
     pub fn unmount_special(&self) -> Result<()> {
         let root_path = self.root_path();
 
-        umount(&root_path.join("proc"))?;
+        umount2(&root_path.join("proc"), MntFlags::MNT_DETACH)?;
         umount(&root_path.join("tmp"))?;
         umount2(&root_path.join("sys"), MntFlags::MNT_DETACH)?;
 
         Ok(())
     }
 
+    pub fn mount_dev<'a, I>(&self, shm_size: u64, devices: I) -> Result<()>
+    where
+        I: Iterator<Item = &'a Device>,
+    {
+        let dev_path = self.root_path().join("dev");
+        create_dir_all(&dev_path)?;
+
+        mount(
+            Some("tmpfs"),
+            &dev_path,
+            Some("tmpfs"),
+            MsFlags::MS_NOSUID | MsFlags::MS_STRICTATIME,
+            Some("mode=755,size=65536k"),
+        )?;
+
+        for device in devices::DEFAULT_DEVICES.iter() {
+            devices::create_node(
+                Path::new(device),
+                &self.host_path_from_container_path(&PathBuf::from(device))?,
+            )?;
+        }
+
+        for device in devices {
+            let target = self.host_path_from_container_path(&device.container_path)?;
+            if let Some(parent) = target.parent() {
+                create_dir_all(parent)?;
+            }
+
+            devices::create_node(&device.host_path, &target)?;
+        }
+
+        let pts_path = dev_path.join("pts");
+        create_dir(&pts_path)?;
+        mount(
+            Some("devpts"),
+            &pts_path,
+            Some("devpts"),
+            MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
+            Some("newinstance,ptmxmode=0666,mode=0620"),
+        )?;
+
+        let shm_path = dev_path.join("shm");
+        create_dir(&shm_path)?;
+        mount(
+            Some("shm"),
+            &shm_path,
+            Some("tmpfs"),
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+            Some(format!("mode=1777,size={}", shm_size).as_str()),
+        )?;
+
+        let mqueue_path = dev_path.join("mqueue");
+        create_dir(&mqueue_path)?;
+        mount(
+            Some("mqueue"),
+            &mqueue_path,
+            Some("mqueue"),
+            MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+            None::<&str>,
+        )?;
+
+        for (source, link) in devices::DEFAULT_SYMLINKS.iter() {
+            symlink(
+                source,
+                self.host_path_from_container_path(&PathBuf::from(link))?,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn unmount_dev(&self) -> Result<()> {
+        umount2(&self.root_path().join("dev"), MntFlags::MNT_DETACH)?;
+
+        Ok(())
+    }
+
     pub fn mount_volumes<'a, I>(&self, volumes: I) -> Result<()>
     where
         I: Iterator<Item = &'a Volume>,
     {
-        for volume in volumes {
+        for volume in mount_order(volumes) {
             let destination_full_path = self.host_path_from_container_path(&volume.destination)?;
 
-            if !destination_full_path.exists() {
-                create_dir(&destination_full_path)?;
+            if let VolumeKind::Tmpfs(data) = &volume.kind {
+                create_dir_all(&destination_full_path)?;
+
+                let mut flags = volume.options.flags;
+                if volume.options.readonly {
+                    flags |= MsFlags::MS_RDONLY;
+                }
+
+                mount(
+                    Some("tmpfs"),
+                    &destination_full_path,
+                    Some("tmpfs"),
+                    flags,
+                    Some(data.as_str()),
+                )?;
+            } else {
+                self.mount_bind(volume, &destination_full_path)?;
+            }
+
+            if let Some(propagation) = volume.options.propagation {
+                mount(
+                    None::<&str>,
+                    &destination_full_path,
+                    None::<&str>,
+                    propagation,
+                    None::<&str>,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn mount_bind(&self, volume: &Volume, destination_full_path: &Path) -> Result<()> {
+        if !destination_full_path.exists() {
+            if volume.source.is_dir() {
+                create_dir_all(destination_full_path)?;
+            } else {
+                if let Some(parent) = destination_full_path.parent() {
+                    create_dir_all(parent)?;
+                }
+                File::create(destination_full_path)?;
+            }
+        }
+
+        if volume.copy_up && destination_full_path.is_dir() {
+            copy_dir(destination_full_path, &volume.source)?;
+        }
+
+        if volume.options.idmap {
+            idmapped::bind(
+                &volume.source,
+                destination_full_path,
+                volume.options.recursive,
+            )?;
+        } else {
+            let mut flags = MsFlags::MS_BIND;
+            if volume.options.recursive {
+                flags |= MsFlags::MS_REC;
             }
 
             mount(
                 Some(&volume.source),
-                &destination_full_path,
+                destination_full_path,
+                None::<&str>,
+                flags,
+                None::<&str>,
+            )?;
+        }
+
+        // bind mount ignores other flags, they have to be set by remount
+        if volume.options.needs_remount() {
+            let mut flags = MsFlags::MS_BIND
+                | MsFlags::MS_REMOUNT
+                | volume.options.flags
+                | locked_flags(destination_full_path)?;
+            if volume.options.readonly {
+                flags |= MsFlags::MS_RDONLY;
+            }
+
+            mount(
+                None::<&str>,
+                destination_full_path,
                 None::<&str>,
-                MsFlags::MS_BIND,
+                flags,
                 None::<&str>,
             )?;
         }
@@ -211,10 +443,173 @@ impl Bundle {
     where
         I: Iterator<Item = &'a Volume>,
     {
-        for volume in volumes {
-            umount(&self.host_path_from_container_path(&volume.destination)?)?;
+        for volume in mount_order(volumes).into_iter().rev() {
+            let destination_full_path = self.host_path_from_container_path(&volume.destination)?;
+
+            if volume.options.recursive {
+                umount2(&destination_full_path, MntFlags::MNT_DETACH)?;
+            } else {
+                umount(&destination_full_path)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Bind mounts file generated by con over `container_path`.
+    pub fn mount_file(&self, source: &Path, container_path: &str) -> Result<()> {
+        let target = self.host_path_from_container_path(&PathBuf::from(container_path))?;
+
+        // symlink would be resolved against host's root
+        if let Ok(metadata) = symlink_metadata(&target) {
+            if metadata.file_type().is_symlink() {
+                remove_file(&target)?;
+            }
         }
 
+        if !target.exists() {
+            if let Some(parent) = target.parent() {
+                create_dir_all(parent)?;
+            }
+            File::create(&target)?;
+        }
+
+        mount(
+            Some(source),
+            &target,
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn unmount_file(&self, container_path: &str) -> Result<()> {
+        umount(&self.host_path_from_container_path(&PathBuf::from(container_path))?)?;
+
         Ok(())
     }
 }
+
+/// Orders volumes so that parents are mounted before nested targets.
+fn mount_order<'a, I>(volumes: I) -> Vec<&'a Volume>
+where
+    I: Iterator<Item = &'a Volume>,
+{
+    let mut volumes: Vec<&Volume> = volumes.collect();
+    volumes.sort_by_key(|volume| volume.destination.components().count());
+
+    volumes
+}
+
+/// Flags of an existing mount which have to be kept on remount, because
+/// mounts inherited from a more privileged user namespace have them locked.
+fn locked_flags(path: &Path) -> Result<MsFlags> {
+    let flags = statvfs(path)?.flags();
+    let mut ms_flags = MsFlags::empty();
+
+    for (fs_flag, ms_flag) in [
+        (FsFlags::ST_NOSUID, MsFlags::MS_NOSUID),
+        (FsFlags::ST_NODEV, MsFlags::MS_NODEV),
+        (FsFlags::ST_NOEXEC, MsFlags::MS_NOEXEC),
+        (FsFlags::ST_NOATIME, MsFlags::MS_NOATIME),
+        (FsFlags::ST_NODIRATIME, MsFlags::MS_NODIRATIME),
+        (FsFlags::ST_RELATIME, MsFlags::MS_RELATIME),
+    ] {
+        if flags.contains(fs_flag) {
+            ms_flags |= ms_flag;
+        }
+    }
+
+    Ok(ms_flags)
+}
+
+/// Makes `path` read-only including the mounts below it, e.g. cgroup file
+/// systems under `/sys`, since remount changes only a single mount.
+fn mount_readonly(path: &Path) -> Result<()> {
+    mount(
+        Some(path),
+        path,
+        None::<&str>,
+        MsFlags::MS_REC | MsFlags::MS_BIND,
+        None::<&str>,
+    )?;
+
+    for mount_point in mount_points(path)? {
+        mount(
+            None::<&str>,
+            &mount_point,
+            None::<&str>,
+            MsFlags::MS_BIND
+                | MsFlags::MS_REMOUNT
+                | MsFlags::MS_RDONLY
+                | locked_flags(&mount_point)?,
+            None::<&str>,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Mount points at `path` and below it, each one listed after its parent.
+fn mount_points(path: &Path) -> Result<Vec<PathBuf>> {
+    let mountinfo = read_to_string("/proc/self/mountinfo")?;
+
+    Ok(mountinfo
+        .lines()
+        .filter_map(|line| line.split(' ').nth(4))
+        .map(unescape_mount_point)
+        .filter(|mount_point| mount_point.starts_with(path))
+        .collect())
+}
+
+/// Decodes octal escapes of space, tab, newline and backslash in mountinfo.
+fn unescape_mount_point(field: &str) -> PathBuf {
+    let mut bytes = vec![];
+    let mut rest = field.as_bytes();
+
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = match tail {
+            [a, b, c, ..] if byte == b'\\' => std::str::from_utf8(&[*a, *b, *c])
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok()),
+            _ => None,
+        };
+
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+
+    PathBuf::from(OsString::from_vec(bytes))
+}
+
+fn mask(path: &Path) -> Result<()> {
+    if path.is_dir() {
+        mount(
+            Some("tmpfs"),
+            path,
+            Some("tmpfs"),
+            MsFlags::MS_RDONLY,
+            None::<&str>,
+        )?;
+    } else {
+        mount(
+            Some("/dev/null"),
+            path,
+            None::<&str>,
+            MsFlags::MS_BIND,
+            None::<&str>,
+        )?;
+    }
+
+    Ok(())
+}
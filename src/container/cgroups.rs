@@ -1,11 +1,23 @@
-use anyhow::Result;
+use std::path::Path;
+
+use anyhow::{anyhow, Result};
 use cgroups_rs::{
-    cgroup_builder::CgroupBuilder, cpu::CpuController, memory::MemController, pid::PidController,
+    cgroup_builder::CgroupBuilder,
+    cpu::CpuController,
+    devices::{DevicePermissions, DeviceType, DevicesController},
+    memory::MemController,
+    pid::PidController,
     Cgroup, CgroupPid, Controller, MaxValue,
 };
 use clap::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::container::{
+    bpf,
+    devices::{DeviceKind, DeviceRule},
+};
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Clone, Debug, Serialize, Deserialize)]
 pub struct Config {
     /// CPU shares (relative weight)
     #[clap(short, long, default_value = "256")]
@@ -15,8 +27,9 @@ pub struct Config {
     #[clap(short, long, default_value = "1073741824")]
     pub(crate) memory: u64,
 
-    /// Tune container pids limit (0 for unlimited)
-    #[clap(short, long, default_value = "0")]
+    /// Tune container pids limit (0 for unlimited), no longer available as -p
+    /// which is short for --publish
+    #[clap(long, default_value = "0")]
     pub(crate) pids_limit: u32,
 }
 
@@ -71,9 +84,54 @@ impl CGroup {
         Ok(())
     }
 
+    /// Restricts access to devices matching `rules`, using devices controller
+    /// on cgroup v1 or device eBPF program on cgroup v2.
+    pub fn allow_devices(&self, rules: &[DeviceRule]) -> Result<()> {
+        if self.inner.v2() {
+            return bpf::attach_device_filter(&Path::new("/sys/fs/cgroup").join(&self.name), rules);
+        }
+
+        let devices_controller: &DevicesController = self
+            .inner
+            .controller_of()
+            .ok_or_else(|| anyhow!("Devices cgroup controller is not mounted"))?;
+        devices_controller.deny_device(DeviceType::All, -1, -1, &device_permissions("rwm"))?;
+
+        for rule in rules {
+            devices_controller.allow_device(
+                device_type(rule.kind),
+                rule.major.map_or(-1, |major| major as i64),
+                rule.minor.map_or(-1, |minor| minor as i64),
+                &device_permissions(&rule.permissions),
+            )?;
+        }
+
+        Ok(())
+    }
+
     pub fn delete(&mut self) -> Result<()> {
         self.inner.delete()?;
 
         Ok(())
     }
 }
+
+fn device_type(kind: DeviceKind) -> DeviceType {
+    match kind {
+        DeviceKind::All => DeviceType::All,
+        DeviceKind::Char => DeviceType::Char,
+        DeviceKind::Block => DeviceType::Block,
+    }
+}
+
+fn device_permissions(permissions: &str) -> Vec<DevicePermissions> {
+    permissions
+        .chars()
+        .filter_map(|c| match c {
+            'r' => Some(DevicePermissions::Read),
+            'w' => Some(DevicePermissions::Write),
+            'm' => Some(DevicePermissions::MkNod),
+            _ => None,
+        })
+        .collect()
+}
@@ -0,0 +1,340 @@
+use std::{
+    ffi::{CString, OsStr},
+    fmt::{self, Display},
+    fs::{read_dir, symlink_metadata, Metadata},
+    os::unix::{
+        ffi::OsStrExt,
+        fs::{FileTypeExt, MetadataExt},
+    },
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use nix::libc;
+
+use crate::container::storage::{OPAQUE_WHITEOUT, WHITEOUT_PREFIX};
+
+/// Extended attributes marking opaque directory, set by kernel overlayfs
+/// (privileged and unprivileged) and by fuse-overlayfs.
+const OPAQUE_XATTRS: [&str; 3] = [
+    "trusted.overlay.opaque",
+    "user.overlay.opaque",
+    "user.fuseoverlayfs.opaque",
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ChangeKind {
+    Added,
+    Changed,
+    Deleted,
+}
+
+impl Display for ChangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added => write!(f, "A"),
+            Self::Changed => write!(f, "C"),
+            Self::Deleted => write!(f, "D"),
+        }
+    }
+}
+
+/// Change of a path in container's file system.
+#[derive(Clone, Debug)]
+pub struct Change {
+    /// Absolute path inside of the container
+    pub path: PathBuf,
+    pub kind: ChangeKind,
+}
+
+/// Whiteout created by overlayfs for a deleted file.
+pub fn is_whiteout(metadata: &Metadata) -> bool {
+    metadata.file_type().is_char_device() && metadata.rdev() == 0
+}
+
+/// Checks whether directory in overlay upperdir hides content of lower
+/// layers.
+pub fn is_opaque(dir: &Path) -> Result<bool> {
+    if dir.join(OPAQUE_WHITEOUT).exists() {
+        return Ok(true);
+    }
+
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    for name in OPAQUE_XATTRS {
+        let name = CString::new(name)?;
+        let mut value = [0u8; 1];
+        let res = unsafe {
+            libc::lgetxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr() as *mut libc::c_void,
+                value.len(),
+            )
+        };
+
+        if res == 1 && value[0] == b'y' {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Checks whether `path` (relative to the root) exists in file system made
+/// of `layers` ordered from the base one.
+pub fn in_layers(layers: &[PathBuf], path: &Path) -> bool {
+    for layer in layers.iter().rev() {
+        if symlink_metadata(layer.join(path)).is_ok() {
+            return true;
+        }
+
+        // whiteout of the path or its parent and opaque parent directory
+        // hide the lower layers
+        for ancestor in path.ancestors() {
+            match (ancestor.parent(), ancestor.file_name()) {
+                (Some(parent), Some(name)) => {
+                    let mut whiteout = WHITEOUT_PREFIX.as_bytes().to_vec();
+                    whiteout.extend(name.as_bytes());
+                    let whiteout = layer.join(parent).join(OsStr::from_bytes(&whiteout));
+
+                    if symlink_metadata(whiteout).is_ok() {
+                        return false;
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        for parent in path.ancestors().skip(1) {
+            if layer.join(parent).join(OPAQUE_WHITEOUT).exists() {
+                return false;
+            }
+        }
+    }
+
+    false
+}
+
+/// Lists changes recorded in overlay `upperdir` on top of image `layers`.
+pub fn changes(upperdir: &Path, layers: &[PathBuf]) -> Result<Vec<Change>> {
+    let mut changes = vec![];
+
+    // container was not started yet or its upperdir was on discarded tmpfs
+    if !upperdir.exists() {
+        return Ok(changes);
+    }
+
+    collect(upperdir, Path::new(""), layers, &mut changes)?;
+    changes.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(changes)
+}
+
+fn collect(
+    upperdir: &Path,
+    dir: &Path,
+    layers: &[PathBuf],
+    changes: &mut Vec<Change>,
+) -> Result<()> {
+    let dir_path = upperdir.join(dir);
+
+    // content of lower layers is deleted, apart from what is in upperdir
+    if !dir.as_os_str().is_empty() && is_opaque(&dir_path)? {
+        for layer in layers {
+            let lower_dir = layer.join(dir);
+            if !lower_dir.is_dir() {
+                continue;
+            }
+
+            for entry in read_dir(lower_dir)? {
+                let name = entry?.file_name();
+                if name.as_bytes().starts_with(WHITEOUT_PREFIX.as_bytes()) {
+                    continue;
+                }
+
+                let path = dir.join(name);
+                if symlink_metadata(upperdir.join(&path)).is_ok()
+                    || !in_layers(layers, &path)
+                    || changes.iter().any(|change| change.path == absolute(&path))
+                {
+                    continue;
+                }
+
+                changes.push(Change {
+                    path: absolute(&path),
+                    kind: ChangeKind::Deleted,
+                });
+            }
+        }
+    }
+
+    for entry in read_dir(&dir_path)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let metadata = entry.path().symlink_metadata()?;
+
+        if name == OPAQUE_WHITEOUT {
+            continue;
+        }
+
+        // whiteout files are used by fuse-overlayfs if it can not create
+        // device nodes
+        if let Some(hidden) = name.as_bytes().strip_prefix(WHITEOUT_PREFIX.as_bytes()) {
+            changes.push(Change {
+                path: absolute(&dir.join(OsStr::from_bytes(hidden))),
+                kind: ChangeKind::Deleted,
+            });
+            continue;
+        }
+
+        let path = dir.join(&name);
+        if is_whiteout(&metadata) {
+            changes.push(Change {
+                path: absolute(&path),
+                kind: ChangeKind::Deleted,
+            });
+            continue;
+        }
+
+        let kind = if in_layers(layers, &path) {
+            ChangeKind::Changed
+        } else {
+            ChangeKind::Added
+        };
+        changes.push(Change {
+            path: absolute(&path),
+            kind,
+        });
+
+        if metadata.is_dir() {
+            collect(upperdir, &path, layers, changes)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    Path::new("/").join(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::{create_dir_all, remove_dir_all, write},
+        process, slice,
+    };
+
+    use super::*;
+
+    /// Creates empty files (and their parent directories) in a new temporary
+    /// directory `name`.
+    fn tree(name: &str, files: &[&str]) -> PathBuf {
+        let dir = env::temp_dir().join(format!("con-changes-{}-{}", process::id(), name));
+        create_dir_all(&dir).unwrap();
+
+        for file in files {
+            let path = dir.join(file);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            write(path, "").unwrap();
+        }
+
+        dir
+    }
+
+    fn listed(upperdir: &Path, layers: &[PathBuf]) -> Vec<String> {
+        changes(upperdir, layers)
+            .unwrap()
+            .iter()
+            .map(|change| format!("{} {}", change.kind, change.path.display()))
+            .collect()
+    }
+
+    #[test]
+    fn whiteouts_are_listed_as_deletions() {
+        let lower = tree("whiteouts-lower", &["etc/hosts", "etc/passwd", "bin/sh"]);
+        let upper = tree(
+            "whiteouts-upper",
+            &["etc/hosts", "etc/new", "etc/.wh.passwd", ".wh.bin"],
+        );
+
+        let changes = listed(&upper, slice::from_ref(&lower));
+        remove_dir_all(&lower).unwrap();
+        remove_dir_all(&upper).unwrap();
+
+        assert_eq!(
+            changes,
+            [
+                "D /bin",
+                "C /etc",
+                "C /etc/hosts",
+                "A /etc/new",
+                "D /etc/passwd",
+            ]
+        );
+    }
+
+    #[test]
+    fn opaque_directory_deletes_visible_lower_content() {
+        let base = tree("opaque-base", &["var/lib/a", "var/lib/b", "var/lib/c"]);
+        // b was deleted already in the image
+        let image = tree("opaque-image", &["var/lib/.wh.b", "var/lib/d"]);
+        let upper = tree("opaque-upper", &["var/lib/.wh..wh..opq", "var/lib/a"]);
+
+        let changes = listed(&upper, &[base.clone(), image.clone()]);
+        for dir in [base, image, upper] {
+            remove_dir_all(dir).unwrap();
+        }
+
+        assert_eq!(
+            changes,
+            [
+                "C /var",
+                "C /var/lib",
+                "C /var/lib/a",
+                "D /var/lib/c",
+                "D /var/lib/d",
+            ]
+        );
+    }
+
+    #[test]
+    fn paths_hidden_in_upper_layers() {
+        let base = tree(
+            "layers-base",
+            &["etc/hosts", "etc/passwd", "opt/app/bin", "srv/data"],
+        );
+        let image = tree(
+            "layers-image",
+            &["etc/.wh.passwd", "opt/app/.wh..wh..opq", ".wh.srv"],
+        );
+        let layers = [base.clone(), image.clone()];
+
+        let cases = [
+            ("etc/hosts", true),
+            ("etc/passwd", false),
+            ("opt/app", true),
+            ("opt/app/bin", false),
+            ("srv/data", false),
+            ("missing", false),
+        ];
+        let visible = cases
+            .iter()
+            .map(|(path, _)| in_layers(&layers, Path::new(path)))
+            .collect::<Vec<_>>();
+        remove_dir_all(base).unwrap();
+        remove_dir_all(image).unwrap();
+
+        for ((path, expected), visible) in cases.iter().zip(visible) {
+            assert_eq!(visible, *expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn missing_upperdir_has_no_changes() {
+        let upper = env::temp_dir().join(format!("con-changes-{}-missing", process::id()));
+
+        assert!(changes(&upper, &[]).unwrap().is_empty());
+    }
+}
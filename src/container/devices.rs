@@ -0,0 +1,187 @@
+use std::{
+    fs::{set_permissions, File, Permissions},
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{bail, Result};
+use nix::{
+    errno::Errno,
+    mount::{mount, MsFlags},
+    sys::stat::{major, minor, mknod, stat, Mode, SFlag},
+};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_DEVICES: [&str; 6] = [
+    "/dev/null",
+    "/dev/zero",
+    "/dev/full",
+    "/dev/random",
+    "/dev/urandom",
+    "/dev/tty",
+];
+
+pub const DEFAULT_SYMLINKS: [(&str, &str); 5] = [
+    ("/proc/self/fd", "/dev/fd"),
+    ("/proc/self/fd/0", "/dev/stdin"),
+    ("/proc/self/fd/1", "/dev/stdout"),
+    ("/proc/self/fd/2", "/dev/stderr"),
+    ("pts/ptmx", "/dev/ptmx"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceKind {
+    All,
+    Char,
+    Block,
+}
+
+/// Device cgroup rule. `None` for major or minor number matches any number.
+#[derive(Debug)]
+pub struct DeviceRule {
+    pub kind: DeviceKind,
+    pub major: Option<u64>,
+    pub minor: Option<u64>,
+    pub permissions: String,
+}
+
+impl DeviceRule {
+    fn new(kind: DeviceKind, major: Option<u64>, minor: Option<u64>, permissions: &str) -> Self {
+        Self {
+            kind,
+            major,
+            minor,
+            permissions: permissions.to_string(),
+        }
+    }
+}
+
+/// Devices every container is allowed to access.
+pub fn default_rules() -> Vec<DeviceRule> {
+    vec![
+        DeviceRule::new(DeviceKind::Char, None, None, "m"),
+        DeviceRule::new(DeviceKind::Block, None, None, "m"),
+        // /dev/null
+        DeviceRule::new(DeviceKind::Char, Some(1), Some(3), "rwm"),
+        // /dev/zero
+        DeviceRule::new(DeviceKind::Char, Some(1), Some(5), "rwm"),
+        // /dev/full
+        DeviceRule::new(DeviceKind::Char, Some(1), Some(7), "rwm"),
+        // /dev/random
+        DeviceRule::new(DeviceKind::Char, Some(1), Some(8), "rwm"),
+        // /dev/urandom
+        DeviceRule::new(DeviceKind::Char, Some(1), Some(9), "rwm"),
+        // /dev/tty
+        DeviceRule::new(DeviceKind::Char, Some(5), Some(0), "rwm"),
+        // /dev/ptmx
+        DeviceRule::new(DeviceKind::Char, Some(5), Some(2), "rwm"),
+        // /dev/pts/*
+        DeviceRule::new(DeviceKind::Char, Some(136), None, "rwm"),
+    ]
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Device {
+    pub host_path: PathBuf,
+    pub container_path: PathBuf,
+    pub permissions: String,
+}
+
+fn is_permissions(s: &str) -> bool {
+    !s.is_empty() && s.len() <= 3 && s.chars().all(|c| "rwm".contains(c))
+}
+
+impl FromStr for Device {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(":").collect();
+
+        let (host_path, container_path, permissions) = match parts.as_slice() {
+            [host_path] => (*host_path, *host_path, "rwm"),
+            [host_path, permissions] if is_permissions(permissions) => {
+                (*host_path, *host_path, *permissions)
+            }
+            [host_path, container_path] => (*host_path, *container_path, "rwm"),
+            [host_path, container_path, permissions] if is_permissions(permissions) => {
+                (*host_path, *container_path, *permissions)
+            }
+            _ => {
+                return Err("Invalid device syntax. \
+                    Expected in format 'host_path[:container_path][:permissions]'")
+            }
+        };
+
+        let host_path = PathBuf::from(host_path);
+        let container_path = PathBuf::from(container_path);
+
+        if !host_path.is_absolute() || !container_path.is_absolute() {
+            return Err("Device paths must be absolute");
+        }
+
+        Ok(Self {
+            host_path,
+            container_path,
+            permissions: permissions.to_string(),
+        })
+    }
+}
+
+impl Device {
+    pub fn rule(&self) -> Result<DeviceRule> {
+        let host_stat = stat(&self.host_path)?;
+
+        let kind = match SFlag::from_bits_truncate(host_stat.st_mode) & SFlag::S_IFMT {
+            SFlag::S_IFCHR => DeviceKind::Char,
+            SFlag::S_IFBLK => DeviceKind::Block,
+            _ => bail!("{} is not a device", self.host_path.display()),
+        };
+
+        Ok(DeviceRule::new(
+            kind,
+            Some(major(host_stat.st_rdev)),
+            Some(minor(host_stat.st_rdev)),
+            &self.permissions,
+        ))
+    }
+}
+
+/// Creates device node at `target` mirroring host device `host_path`.
+/// Creating device nodes is not permitted inside user namespace, so in that
+/// case host node is bind mounted instead.
+pub fn create_node(host_path: &Path, target: &Path) -> Result<()> {
+    let host_stat = stat(host_path)?;
+    let kind = SFlag::from_bits_truncate(host_stat.st_mode) & SFlag::S_IFMT;
+
+    if kind != SFlag::S_IFCHR && kind != SFlag::S_IFBLK {
+        bail!("{} is not a device", host_path.display());
+    }
+
+    let mode = host_stat.st_mode & 0o7777;
+
+    match mknod(
+        target,
+        kind,
+        Mode::from_bits_truncate(mode),
+        host_stat.st_rdev,
+    ) {
+        Ok(_) => {
+            set_permissions(target, Permissions::from_mode(mode))?;
+        }
+        Err(Errno::EPERM) => {
+            File::create(target)?;
+
+            mount(
+                Some(host_path),
+                target,
+                None::<&str>,
+                MsFlags::MS_BIND,
+                None::<&str>,
+            )?;
+        }
+        Err(err) => return Err(err.into()),
+    }
+
+    Ok(())
+}
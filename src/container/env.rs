@@ -1,8 +1,9 @@
 use std::str::FromStr;
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct EnvVariable {
     pub key: String,
     pub value: String,
@@ -0,0 +1,337 @@
+use std::{
+    collections::BTreeSet,
+    ffi::{OsStr, OsString},
+    fs::{
+        create_dir, create_dir_all, read_dir, read_link, remove_file, symlink_metadata, Metadata,
+    },
+    os::unix::ffi::OsStrExt,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    container::{
+        bundle::Bundle,
+        changes::{is_opaque, is_whiteout},
+        spec::Spec,
+        state::State,
+        storage::WHITEOUT_PREFIX,
+    },
+    image::Image,
+    util::copy_metadata,
+    volume::VolumeKind,
+};
+
+/// Limit of symbolic links followed when resolving a path.
+const MAX_SYMLINKS: usize = 40;
+
+/// File system of a container accessed from the host. Root of a running
+/// container (or of a stopped one with vfs driver) is used as is, stopped
+/// containers with overlay drivers are read from their layers.
+pub struct ContainerFs {
+    /// Writable layer first, followed by image layers from the topmost one
+    layers: Vec<PathBuf>,
+    /// Destinations (relative to the root) and sources of volumes of a
+    /// stopped container, the ones of running container are mounted
+    volumes: Vec<(PathBuf, PathBuf)>,
+    /// Writable layer of stopped container is replaced by tmpfs on start
+    volatile: bool,
+}
+
+impl ContainerFs {
+    pub fn new(state: &State, spec: &Spec) -> Result<Self> {
+        let dir = state.dir()?;
+        let root = Bundle::root_path_inner(&dir);
+
+        // mounts are visible only in container's mount namespace
+        if let Some(pid) = state.pid.filter(|_| state.is_running()) {
+            return Ok(Self {
+                layers: vec![
+                    PathBuf::from(format!("/proc/{}/root", pid)).join(root.strip_prefix("/")?)
+                ],
+                volumes: vec![],
+                volatile: false,
+            });
+        }
+
+        let volumes = spec
+            .volumes
+            .iter()
+            .filter(|volume| !matches!(volume.kind, VolumeKind::Tmpfs(_)))
+            .map(|volume| {
+                (
+                    relative(&volume.destination).to_path_buf(),
+                    volume.source.clone(),
+                )
+            })
+            .collect();
+
+        let layers = match state.upperdir()? {
+            Some(upperdir) => {
+                let image = Image::new(
+                    spec.image.name.clone(),
+                    spec.image.tag.clone(),
+                    spec.image_path.clone(),
+                )?;
+
+                let mut layers = vec![upperdir];
+                layers.extend(image.layer_paths().into_iter().rev());
+                layers
+            }
+            None => vec![root],
+        };
+
+        Ok(Self {
+            layers,
+            volumes,
+            volatile: state.storage_size.is_some(),
+        })
+    }
+
+    fn is_layered(&self) -> bool {
+        self.layers.len() > 1
+    }
+
+    /// Volume `path` (relative to the root) is in, the most nested one wins.
+    fn volume(&self, path: &Path) -> Option<&(PathBuf, PathBuf)> {
+        self.volumes
+            .iter()
+            .filter(|(destination, _)| path.starts_with(destination))
+            .max_by_key(|(destination, _)| destination.components().count())
+    }
+
+    /// Host paths `path` (relative to the root) is made of, the topmost
+    /// first. Empty if the path does not exist.
+    fn sources(&self, path: &Path) -> Result<Vec<PathBuf>> {
+        if let Some((destination, source)) = self.volume(path) {
+            let source = source.join(path.strip_prefix(destination)?);
+            return Ok(match symlink_metadata(&source) {
+                Ok(_) => vec![source],
+                Err(_) => vec![],
+            });
+        }
+
+        if !self.is_layered() {
+            let source = self.layers[0].join(path);
+            return Ok(match symlink_metadata(&source) {
+                Ok(_) => vec![source],
+                Err(_) => vec![],
+            });
+        }
+
+        let mut sources = vec![];
+        for layer in &self.layers {
+            if is_hidden(layer, path) {
+                break;
+            }
+
+            let source = layer.join(path);
+            match symlink_metadata(&source) {
+                // only directories are merged with the lower layers
+                Ok(metadata) if sources.is_empty() || metadata.is_dir() => {
+                    let merged = metadata.is_dir() && !is_opaque(&source)?;
+                    sources.push(source);
+                    if !merged {
+                        break;
+                    }
+                }
+                Ok(_) => break,
+                Err(_) => {}
+            }
+
+            for parent in path.ancestors().skip(1) {
+                if !parent.as_os_str().is_empty() && is_opaque(&layer.join(parent))? {
+                    return Ok(sources);
+                }
+            }
+        }
+
+        Ok(sources)
+    }
+
+    /// Host path of `path` (relative to the root) and its metadata, `None`
+    /// if it does not exist.
+    pub fn lookup(&self, path: &Path) -> Result<Option<(PathBuf, Metadata)>> {
+        match self.sources(path)?.into_iter().next() {
+            Some(source) => {
+                let metadata = symlink_metadata(&source)?;
+                Ok(Some((source, metadata)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Names of entries in directory `path` (relative to the root).
+    pub fn read_dir(&self, path: &Path) -> Result<Vec<OsString>> {
+        let mut names = BTreeSet::new();
+        for source in self.sources(path)? {
+            for entry in read_dir(source)? {
+                let name = entry?.file_name();
+                if self.is_layered() && name.as_bytes().starts_with(WHITEOUT_PREFIX.as_bytes()) {
+                    continue;
+                }
+
+                names.insert(name);
+            }
+        }
+
+        // mount points of volumes do not have to exist in the layers
+        for (destination, _) in &self.volumes {
+            if destination.parent() == Some(path) {
+                if let Some(name) = destination.file_name() {
+                    names.insert(name.to_os_string());
+                }
+            }
+        }
+
+        let mut entries = vec![];
+        for name in names {
+            if !self.sources(&path.join(&name))?.is_empty() {
+                entries.push(name);
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Resolves symbolic links in container `path` without leaving the
+    /// container root, the last component is followed only with `follow`.
+    /// Returns path relative to the root.
+    pub fn resolve(&self, path: &Path, follow: bool) -> Result<PathBuf> {
+        let mut resolved = PathBuf::new();
+        let mut pending = vec![];
+        push_components(&mut pending, path);
+        let mut followed = 0;
+
+        while let Some(component) = pending.pop() {
+            if component == ".." {
+                resolved.pop();
+                continue;
+            }
+
+            let candidate = resolved.join(&component);
+            if pending.is_empty() && !follow {
+                resolved = candidate;
+                break;
+            }
+
+            match self.lookup(&candidate)? {
+                Some((source, metadata)) if metadata.file_type().is_symlink() => {
+                    followed += 1;
+                    if followed > MAX_SYMLINKS {
+                        bail!("Too many levels of symbolic links in {}", path.display());
+                    }
+
+                    let target = read_link(source)?;
+                    if target.is_absolute() {
+                        resolved = PathBuf::new();
+                    }
+                    push_components(&mut pending, &target);
+                }
+                _ => resolved = candidate,
+            }
+        }
+
+        Ok(resolved)
+    }
+
+    /// Host path where entry at `path` (relative to the root) can be
+    /// created. Existing entry is removed unless it is a directory, missing
+    /// parent directories are created in the writable layer.
+    pub fn create_path(&self, path: &Path) -> Result<PathBuf> {
+        let host_path = if self.volume(path).is_some() || !self.is_layered() {
+            let host_path = match self.volume(path) {
+                Some((destination, source)) => source.join(path.strip_prefix(destination)?),
+                None => self.layers[0].join(path),
+            };
+            if let Some(parent) = host_path.parent() {
+                create_dir_all(parent)?;
+            }
+
+            host_path
+        } else {
+            if self.volatile {
+                bail!(
+                    "Writable layer of container with size limit exists only while it is running"
+                );
+            }
+            let upperdir = &self.layers[0];
+
+            // parent directories from lower layers are copied up
+            let mut parents = path.ancestors().skip(1).collect::<Vec<_>>();
+            parents.reverse();
+            for parent in parents {
+                let upper = upperdir.join(parent);
+                if parent.as_os_str().is_empty() || symlink_metadata(&upper).is_ok() {
+                    continue;
+                }
+
+                create_dir(&upper)?;
+                if let Some((_, metadata)) = self.lookup(parent)? {
+                    copy_metadata(&metadata, &upper)?;
+                }
+            }
+
+            // new directory would need opaque marker the host user can not
+            // always set, files only shadow the lower ones
+            if is_hidden(upperdir, path) {
+                bail!(
+                    "{} was deleted in the container, start it to copy over it",
+                    Path::new("/").join(path).display()
+                );
+            }
+
+            upperdir.join(path)
+        };
+
+        match symlink_metadata(&host_path) {
+            Ok(metadata) if !metadata.is_dir() => remove_file(&host_path)?,
+            _ => {}
+        }
+
+        Ok(host_path)
+    }
+}
+
+/// Container path relative to its root.
+pub fn relative(path: &Path) -> &Path {
+    path.strip_prefix("/").unwrap_or(path)
+}
+
+/// Checks whether `path` or one of its parents is deleted in `layer`.
+fn is_hidden(layer: &Path, path: &Path) -> bool {
+    for ancestor in path.ancestors() {
+        let (parent, name) = match (ancestor.parent(), ancestor.file_name()) {
+            (Some(parent), Some(name)) => (parent, name),
+            _ => break,
+        };
+
+        let mut whiteout = WHITEOUT_PREFIX.as_bytes().to_vec();
+        whiteout.extend(name.as_bytes());
+        if symlink_metadata(layer.join(parent).join(OsStr::from_bytes(&whiteout))).is_ok() {
+            return true;
+        }
+
+        match symlink_metadata(layer.join(ancestor)) {
+            Ok(metadata) if is_whiteout(&metadata) => return true,
+            // file replacing directory of the lower layers
+            Ok(metadata) if ancestor != path && !metadata.is_dir() => return true,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+/// Pushes components of `path` in reverse order, so that they can be popped
+/// from the first one.
+fn push_components(pending: &mut Vec<OsString>, path: &Path) {
+    for component in path.components().rev() {
+        match component {
+            Component::Normal(name) => pending.push(name.to_os_string()),
+            Component::ParentDir => pending.push(OsString::from("..")),
+            Component::RootDir | Component::CurDir | Component::Prefix(_) => {}
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::{
+    ffi::CString,
+    fs::write,
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::Result;
+use nix::{errno::Errno, libc};
+use serde::{Deserialize, Serialize};
+
+use crate::container::{ipam::Network, state::State};
+
+const LOCALHOST_ENTRIES: &str = "127.0.0.1\tlocalhost\n\
+    ::1\tlocalhost ip6-localhost ip6-loopback\n";
+/// Address of container's own host name when it has no address of its own
+pub const LOOPBACK_ADDRESS: Ipv4Addr = Ipv4Addr::new(127, 0, 1, 1);
+
+/// Custom host-to-IP mapping added to container's hosts file.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HostEntry {
+    pub hostname: String,
+    pub address: IpAddr,
+}
+
+impl FromStr for HostEntry {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(":") {
+            Some((hostname, address)) if !hostname.is_empty() => match address.parse() {
+                Ok(address) => Ok(Self {
+                    hostname: hostname.to_string(),
+                    address,
+                }),
+                Err(_) => Err("Invalid IP address of the host"),
+            },
+            _ => Err("Invalid host syntax. Expected in format 'host:ip'"),
+        }
+    }
+}
+
+/// Generated hosts file bind mounted to container's /etc/hosts.
+pub fn hosts_path(state: &State) -> Result<PathBuf> {
+    Ok(state.dir()?.join("hosts"))
+}
+
+/// Generated hostname file bind mounted to container's /etc/hostname.
+pub fn hostname_path(state: &State) -> Result<PathBuf> {
+    Ok(state.dir()?.join("hostname"))
+}
+
+/// Writes container's hosts file with its own `address` and addresses of
+/// `peers` reachable by name.
+pub fn write_hosts(state: &State, address: IpAddr, peers: &[(Ipv4Addr, State)]) -> Result<()> {
+    let mut content = String::from(LOCALHOST_ENTRIES);
+
+    if state.domainname.is_empty() {
+        content.push_str(&format!("{}\t{}\n", address, state.hostname));
+    } else {
+        content.push_str(&format!(
+            "{}\t{}.{} {}\n",
+            address, state.hostname, state.domainname, state.hostname
+        ));
+    }
+
+    for (peer_address, peer) in peers {
+        if peer.id != state.id {
+            content.push_str(&format!("{}\t{}\n", peer_address, peer.name));
+        }
+    }
+
+    for entry in &state.extra_hosts {
+        content.push_str(&format!("{}\t{}\n", entry.address, entry.hostname));
+    }
+
+    // written in place to keep the mount of a running container valid
+    write(hosts_path(state)?, content)?;
+
+    Ok(())
+}
+
+pub fn write_hostname(state: &State) -> Result<()> {
+    write(hostname_path(state)?, format!("{}\n", state.hostname))?;
+
+    Ok(())
+}
+
+/// Regenerates hosts files of all containers attached to `network`, so they
+/// can reach each other by name.
+pub fn refresh(network: &Network) -> Result<()> {
+    let containers = network.containers()?;
+
+    for (address, state) in &containers {
+        if state.dir()?.exists() {
+            write_hosts(state, IpAddr::V4(*address), &containers)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn set_domainname(name: &str) -> Result<()> {
+    let name = CString::new(name)?;
+    let res = unsafe { libc::setdomainname(name.as_ptr(), name.as_bytes().len()) };
+    Errno::result(res)?;
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::{fs::read_to_string, io::ErrorKind, path::Path, str::FromStr};
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+
+/// Owner of files whose ids are not mapped into the container.
+pub const OVERFLOW_ID: u32 = 65534;
+
+/// Mapping of `size` consecutive ids starting with `container_id` inside of
+/// user namespace to ids starting with `host_id` outside of it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct IdMap {
+    pub container_id: u32,
+    pub host_id: u32,
+    pub size: u32,
+}
+
+impl FromStr for IdMap {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(":").collect();
+
+        match parts.as_slice() {
+            [container_id, host_id, size] => {
+                match (container_id.parse(), host_id.parse(), size.parse()) {
+                    (Ok(container_id), Ok(host_id), Ok(size)) => Ok(Self {
+                        container_id,
+                        host_id,
+                        size,
+                    }),
+                    _ => Err("Ids and size have to be non-negative numbers"),
+                }
+            }
+            _ => Err("Invalid id mapping syntax. Expected in format 'container_id:host_id:size'"),
+        }
+    }
+}
+
+impl IdMap {
+    pub fn args(&self) -> [String; 3] {
+        [
+            self.container_id.to_string(),
+            self.host_id.to_string(),
+            self.size.to_string(),
+        ]
+    }
+}
+
+/// Maps `id` (uid or gid) of the current user to root inside of the
+/// container and the rest of container ids to subordinate ranges from
+/// `subid_path` (`/etc/subuid` or `/etc/subgid`), which are owned by user
+/// `name` or `uid` in both files.
+pub fn default_mappings(
+    subid_path: &Path,
+    id: u32,
+    uid: u32,
+    name: Option<&str>,
+) -> Result<Vec<IdMap>> {
+    let mut mappings = vec![IdMap {
+        container_id: 0,
+        host_id: id,
+        size: 1,
+    }];
+
+    let mut container_id = 1;
+    for (host_id, size) in subordinate_ranges(subid_path, uid, name)? {
+        mappings.push(IdMap {
+            container_id,
+            host_id,
+            size,
+        });
+
+        container_id = match container_id.checked_add(size) {
+            Some(container_id) => container_id,
+            None => bail!(
+                "Subordinate id ranges in {} exceed the id range",
+                subid_path.display()
+            ),
+        };
+    }
+
+    Ok(mappings)
+}
+
+fn subordinate_ranges(subid_path: &Path, uid: u32, name: Option<&str>) -> Result<Vec<(u32, u32)>> {
+    let content = match read_to_string(subid_path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    let uid = uid.to_string();
+
+    let ranges = content
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("#"))
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.trim().split(":").collect();
+
+            match parts.as_slice() {
+                [owner, start, count] if *owner == uid || Some(*owner) == name => {
+                    match (start.parse(), count.parse()) {
+                        (Ok(start), Ok(count)) => Some((start, count)),
+                        _ => None,
+                    }
+                }
+                _ => None,
+            }
+        })
+        .collect();
+
+    Ok(ranges)
+}
+
+/// Translates id from outside of the user namespace to the one seen inside of
+/// it, `None` if the id is not mapped.
+pub fn to_container(mappings: &[IdMap], host_id: u32) -> Option<u32> {
+    mappings.iter().find_map(|mapping| {
+        let offset = host_id.checked_sub(mapping.host_id)?;
+        (offset < mapping.size).then(|| mapping.container_id + offset)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVALID_SYNTAX: &str =
+        "Invalid id mapping syntax. Expected in format 'container_id:host_id:size'";
+    const INVALID_NUMBER: &str = "Ids and size have to be non-negative numbers";
+
+    #[test]
+    fn parse_id_map() {
+        let cases = [
+            ("0:1000:1", Ok([0, 1000, 1])),
+            ("1:100000:65536", Ok([1, 100000, 65536])),
+            ("0:0:4294967295", Ok([0, 0, u32::MAX])),
+            ("0:1000", Err(INVALID_SYNTAX)),
+            ("0:1000:1:1", Err(INVALID_SYNTAX)),
+            ("", Err(INVALID_SYNTAX)),
+            ("-1:1000:1", Err(INVALID_NUMBER)),
+            ("0:1000:4294967296", Err(INVALID_NUMBER)),
+            ("root:1000:1", Err(INVALID_NUMBER)),
+            ("0::1", Err(INVALID_NUMBER)),
+        ];
+
+        for (input, expected) in cases {
+            let map = IdMap::from_str(input).map(|map| [map.container_id, map.host_id, map.size]);
+            assert_eq!(map, expected, "{}", input);
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use std::{ffi::CString, mem::size_of, os::unix::ffi::OsStrExt, path::Path};
+
+use anyhow::{bail, Result};
+use nix::{
+    errno::Errno,
+    fcntl::{open, OFlag},
+    libc,
+    sys::stat::Mode,
+    unistd::close,
+};
+
+const OPEN_TREE_CLONE: libc::c_uint = 1;
+const AT_RECURSIVE: libc::c_uint = 0x8000;
+const MOVE_MOUNT_F_EMPTY_PATH: libc::c_uint = 0x4;
+const MOUNT_ATTR_IDMAP: u64 = 0x0010_0000;
+
+#[repr(C)]
+#[derive(Default)]
+struct MountAttr {
+    attr_set: u64,
+    attr_clr: u64,
+    propagation: u64,
+    userns_fd: u64,
+}
+
+/// Bind mounts `source` to `destination` with ids mapped through user
+/// namespace of the current process, so files owned by host ids appear owned
+/// by the same ids inside of the container.
+pub fn bind(source: &Path, destination: &Path, recursive: bool) -> Result<()> {
+    let source_c = CString::new(source.as_os_str().as_bytes())?;
+    let destination_c = CString::new(destination.as_os_str().as_bytes())?;
+    let empty = CString::default();
+
+    let mut flags = OPEN_TREE_CLONE | libc::O_CLOEXEC as libc::c_uint;
+    if recursive {
+        flags |= AT_RECURSIVE;
+    }
+
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_open_tree,
+            libc::AT_FDCWD,
+            source_c.as_ptr(),
+            flags,
+        )
+    };
+    let tree_fd = match Errno::result(res) {
+        Ok(fd) => fd as i32,
+        Err(Errno::ENOSYS) => bail!("Idmapped mounts require Linux 5.12 or newer"),
+        Err(err) => return Err(err.into()),
+    };
+
+    let result = (|| -> Result<()> {
+        let userns_fd = open(
+            "/proc/self/ns/user",
+            OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )?;
+
+        let attr = MountAttr {
+            attr_set: MOUNT_ATTR_IDMAP,
+            userns_fd: userns_fd as u64,
+            ..Default::default()
+        };
+
+        let mut flags = libc::AT_EMPTY_PATH as libc::c_uint;
+        if recursive {
+            flags |= AT_RECURSIVE;
+        }
+
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                tree_fd,
+                empty.as_ptr(),
+                flags,
+                &attr as *const MountAttr,
+                size_of::<MountAttr>(),
+            )
+        };
+        close(userns_fd)?;
+
+        match Errno::result(res) {
+            Ok(_) => {}
+            Err(Errno::EINVAL | Errno::EPERM | Errno::EOPNOTSUPP) => bail!(
+                "File system of {} does not support idmapped mounts \
+                 (unprivileged users can idmap only mounts they own)",
+                source.display()
+            ),
+            Err(err) => return Err(err.into()),
+        }
+
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_move_mount,
+                tree_fd,
+                empty.as_ptr(),
+                libc::AT_FDCWD,
+                destination_c.as_ptr(),
+                MOVE_MOUNT_F_EMPTY_PATH,
+            )
+        };
+        Errno::result(res)?;
+
+        Ok(())
+    })();
+
+    close(tree_fd)?;
+
+    result
+}
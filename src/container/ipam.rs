@@ -0,0 +1,414 @@
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt,
+    fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, write, File},
+    io::ErrorKind,
+    net::Ipv4Addr,
+    os::unix::io::AsRawFd,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use anyhow::{anyhow, bail, Result};
+use nix::fcntl::{flock, FlockArg};
+use serde::{Deserialize, Serialize};
+
+use crate::{container::state::State, util::data_root};
+
+pub const DEFAULT_NETWORK: &str = "bridge";
+pub const DEFAULT_BRIDGE: &str = "con0";
+const BRIDGE_PREFIX: &str = "con";
+pub const DEFAULT_SUBNET: &str = "10.88.0.0/16";
+/// User-defined networks get a /24 from this range unless subnet is given
+const USER_SUBNETS_START: Ipv4Addr = Ipv4Addr::new(10, 89, 0, 0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Subnet {
+    pub address: Ipv4Addr,
+    pub prefix_len: u8,
+}
+
+impl FromStr for Subnet {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (address, prefix_len) = match s.split_once("/") {
+            Some(parts) => parts,
+            None => return Err("Invalid subnet syntax. Expected in format 'address/prefix_len'"),
+        };
+
+        let address: Ipv4Addr = match address.parse() {
+            Ok(address) => address,
+            Err(_) => return Err("Invalid subnet address"),
+        };
+
+        let prefix_len = match prefix_len.parse() {
+            Ok(prefix_len) if (8..=30).contains(&prefix_len) => prefix_len,
+            _ => return Err("Subnet prefix length has to be between 8 and 30"),
+        };
+
+        let subnet = Self {
+            address,
+            prefix_len,
+        };
+
+        Ok(Self {
+            address: Ipv4Addr::from(u32::from(address) & subnet.mask()),
+            prefix_len,
+        })
+    }
+}
+
+impl TryFrom<String> for Subnet {
+    type Error = &'static str;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::from_str(&s)
+    }
+}
+
+impl From<Subnet> for String {
+    fn from(subnet: Subnet) -> Self {
+        subnet.to_string()
+    }
+}
+
+impl fmt::Display for Subnet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.address, self.prefix_len)
+    }
+}
+
+impl Subnet {
+    fn mask(&self) -> u32 {
+        u32::MAX << (32 - self.prefix_len)
+    }
+
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        u32::from(address) & self.mask() == u32::from(self.address)
+    }
+
+    pub fn overlaps(&self, other: &Subnet) -> bool {
+        self.contains(other.address) || other.contains(self.address)
+    }
+
+    /// Usable host addresses, without network and broadcast address.
+    pub fn hosts(&self) -> impl Iterator<Item = Ipv4Addr> {
+        let first = u32::from(self.address) + 1;
+        let last = u32::from(self.address) | !self.mask();
+
+        (first..last).map(Ipv4Addr::from)
+    }
+}
+
+pub fn networks_dir() -> Result<PathBuf> {
+    Ok(data_root()?.join("networks"))
+}
+
+/// Bridge network persisted in con's state directory, together with address
+/// leases of containers attached to it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Network {
+    pub name: String,
+    pub bridge: String,
+    pub subnet: Subnet,
+    pub gateway: Ipv4Addr,
+}
+
+impl Network {
+    pub fn new(name: String, bridge: String, subnet: Subnet) -> Self {
+        let gateway = subnet.hosts().next().unwrap();
+
+        Self {
+            name,
+            bridge,
+            subnet,
+            gateway,
+        }
+    }
+
+    fn dir(&self) -> Result<PathBuf> {
+        Ok(networks_dir()?.join(&self.name))
+    }
+
+    pub fn load(name: &str) -> Result<Self> {
+        let content = match read_to_string(networks_dir()?.join(name).join("network.json")) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => {
+                bail!("No such network: {}", name)
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = self.dir()?;
+        create_dir_all(&dir)?;
+        write(
+            dir.join("network.json"),
+            serde_json::to_string_pretty(self)?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn list() -> Result<Vec<Self>> {
+        let entries = match read_dir(networks_dir()?) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut networks = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.join("network.json").exists() {
+                let content = read_to_string(path.join("network.json"))?;
+                networks.push(serde_json::from_str(&content)?);
+            }
+        }
+
+        Ok(networks)
+    }
+
+    /// Loads default bridge network, creating it with `subnet` if it does not
+    /// exist yet.
+    pub fn default_bridge(subnet: Option<Subnet>) -> Result<Self> {
+        match Self::load(DEFAULT_NETWORK) {
+            Ok(network) => {
+                if let Some(subnet) = subnet {
+                    if subnet != network.subnet {
+                        bail!(
+                            "Network {} already exists with subnet {}",
+                            network.name,
+                            network.subnet
+                        );
+                    }
+                }
+
+                Ok(network)
+            }
+            Err(_) => {
+                let subnet = match subnet {
+                    Some(subnet) => subnet,
+                    None => Subnet::from_str(DEFAULT_SUBNET).map_err(|err| anyhow!(err))?,
+                };
+
+                let network = Self::new(
+                    DEFAULT_NETWORK.to_string(),
+                    DEFAULT_BRIDGE.to_string(),
+                    subnet,
+                );
+                network.save()?;
+
+                Ok(network)
+            }
+        }
+    }
+
+    /// Creates user-defined network with its own bridge.
+    pub fn create(name: &str, subnet: Option<Subnet>) -> Result<Self> {
+        if !is_valid_name(name) {
+            bail!("Invalid network name {}", name);
+        }
+
+        let networks = Self::list()?;
+        if name == DEFAULT_NETWORK || networks.iter().any(|network| network.name == name) {
+            bail!("Network {} already exists", name);
+        }
+
+        let subnet = match subnet {
+            Some(subnet) => {
+                if let Some(other) = networks
+                    .iter()
+                    .find(|network| network.subnet.overlaps(&subnet))
+                {
+                    bail!("Subnet {} overlaps with network {}", subnet, other.name);
+                }
+
+                subnet
+            }
+            None => (0..=255)
+                .map(|index| Subnet {
+                    address: Ipv4Addr::from(u32::from(USER_SUBNETS_START) + (index << 8)),
+                    prefix_len: 24,
+                })
+                .find(|subnet| {
+                    !networks
+                        .iter()
+                        .any(|network| network.subnet.overlaps(subnet))
+                })
+                .ok_or_else(|| anyhow!("No free subnet left for network {}", name))?,
+        };
+
+        let bridge = (1..)
+            .map(|index| format!("{}{}", BRIDGE_PREFIX, index))
+            .find(|bridge| !networks.iter().any(|network| &network.bridge == bridge))
+            .unwrap();
+
+        let network = Self::new(name.to_string(), bridge, subnet);
+        network.save()?;
+
+        Ok(network)
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        if self.name == DEFAULT_NETWORK {
+            bail!("Default network {} can not be removed", self.name);
+        }
+
+        if !self.containers()?.is_empty() {
+            bail!("Network {} has attached containers", self.name);
+        }
+
+        remove_dir_all(self.dir()?)?;
+
+        Ok(())
+    }
+
+    fn with_leases<F, T>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut BTreeMap<Ipv4Addr, String>) -> Result<T>,
+    {
+        let dir = self.dir()?;
+        create_dir_all(&dir)?;
+
+        let lock = File::create(dir.join("leases.lock"))?;
+        flock(lock.as_raw_fd(), FlockArg::LockExclusive)?;
+
+        let leases_path = dir.join("leases.json");
+        let mut leases = match read_to_string(&leases_path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(err) if err.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(err.into()),
+        };
+
+        let result = f(&mut leases)?;
+        write(&leases_path, serde_json::to_string_pretty(&leases)?)?;
+
+        Ok(result)
+    }
+
+    /// Addresses leased to containers.
+    pub fn leases(&self) -> Result<BTreeMap<Ipv4Addr, String>> {
+        self.with_leases(|leases| Ok(leases.clone()))
+    }
+
+    /// Attached containers which still exist, together with their addresses.
+    pub fn containers(&self) -> Result<Vec<(Ipv4Addr, State)>> {
+        let states = State::list()?;
+
+        Ok(self
+            .leases()?
+            .into_iter()
+            .filter_map(|(address, id)| {
+                states
+                    .iter()
+                    .find(|state| state.id == id)
+                    .map(|state| (address, state.clone()))
+            })
+            .collect())
+    }
+
+    pub fn allocate(&self, container_id: &str) -> Result<Lease> {
+        let container_ids: Vec<String> = State::list()?.into_iter().map(|state| state.id).collect();
+
+        self.with_leases(|leases| {
+            // leases of containers which no longer exist
+            leases.retain(|_, id| container_ids.contains(id));
+
+            let address = self
+                .subnet
+                .hosts()
+                .find(|address| *address != self.gateway && !leases.contains_key(address))
+                .ok_or_else(|| anyhow!("No free addresses left in network {}", self.name))?;
+
+            leases.insert(address, container_id.to_string());
+
+            Ok(Lease {
+                network: self.clone(),
+                address,
+                container_id: container_id.to_string(),
+                released: false,
+            })
+        })
+    }
+
+    pub fn release(&self, container_id: &str) -> Result<()> {
+        self.with_leases(|leases| {
+            leases.retain(|_, id| id != container_id);
+
+            Ok(())
+        })
+    }
+}
+
+/// Address leased to a container, released when dropped unless it was
+/// released explicitly before.
+pub struct Lease {
+    pub network: Network,
+    pub address: Ipv4Addr,
+    container_id: String,
+    released: bool,
+}
+
+impl Lease {
+    pub fn release(&mut self) -> Result<()> {
+        self.network.release(&self.container_id)?;
+        self.released = true;
+
+        Ok(())
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if !self.released {
+            let _ = self.network.release(&self.container_id);
+        }
+    }
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVALID_PREFIX_LEN: &str = "Subnet prefix length has to be between 8 and 30";
+
+    #[test]
+    fn parse_subnet() {
+        let cases = [
+            ("10.88.0.0/16", Ok("10.88.0.0/16")),
+            ("10.88.1.5/16", Ok("10.88.0.0/16")),
+            ("10.255.255.255/8", Ok("10.0.0.0/8")),
+            ("192.168.1.7/30", Ok("192.168.1.4/30")),
+            (
+                "10.88.0.0",
+                Err("Invalid subnet syntax. Expected in format 'address/prefix_len'"),
+            ),
+            ("10.88.0/16", Err("Invalid subnet address")),
+            ("fd00::/64", Err("Invalid subnet address")),
+            ("/16", Err("Invalid subnet address")),
+            ("10.0.0.0/7", Err(INVALID_PREFIX_LEN)),
+            ("10.0.0.0/31", Err(INVALID_PREFIX_LEN)),
+            ("10.0.0.0/", Err(INVALID_PREFIX_LEN)),
+            ("10.0.0.0/-8", Err(INVALID_PREFIX_LEN)),
+        ];
+
+        for (input, expected) in cases {
+            let subnet = Subnet::from_str(input).map(|subnet| subnet.to_string());
+            assert_eq!(subnet, expected.map(String::from), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    ffi::OsStr,
+    fs::{read_dir, File},
+    io::{self, Write},
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::Path,
+};
+
+use anyhow::Result;
+use flate2::{write::GzEncoder, Compression};
+use sha2::{Digest, Sha256};
+use tar::{Builder, EntryType, Header};
+
+use crate::container::{
+    changes::{is_opaque, is_whiteout},
+    idmap::{self, IdMap, OVERFLOW_ID},
+    storage::{OPAQUE_WHITEOUT, WHITEOUT_PREFIX},
+};
+
+/// Image layer written from container's writable layer.
+#[derive(Debug)]
+pub struct Layer {
+    /// Hex encoded sha256 of the compressed layer
+    pub digest: String,
+    /// Hex encoded sha256 of the uncompressed layer
+    pub diff_id: String,
+    /// Size of the compressed layer
+    pub size: u64,
+}
+
+/// Computes sha256 and size of the data passed through.
+struct HashingWriter<W: Write> {
+    inner: W,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+            size: 0,
+        }
+    }
+
+    fn finish(self) -> (W, String, u64) {
+        (
+            self.inner,
+            format!("{:x}", self.hasher.finalize()),
+            self.size,
+        )
+    }
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.size += written as u64;
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writes content of overlay `upperdir` to `path` as gzipped OCI layer.
+/// Overlay whiteouts are converted to `.wh.` files and owners are translated
+/// to ids inside of the container's user namespace.
+pub fn write_layer(
+    upperdir: &Path,
+    path: &Path,
+    uid_mappings: &[IdMap],
+    gid_mappings: &[IdMap],
+) -> Result<Layer> {
+    let compressed = HashingWriter::new(File::create(path)?);
+    let uncompressed = HashingWriter::new(GzEncoder::new(compressed, Compression::default()));
+
+    let mut builder = Builder::new(uncompressed);
+    builder.follow_symlinks(false);
+    append_dir(
+        &mut builder,
+        upperdir,
+        Path::new(""),
+        uid_mappings,
+        gid_mappings,
+    )?;
+
+    let (encoder, diff_id, _) = builder.into_inner()?.finish();
+    let (mut file, digest, size) = encoder.finish()?.finish();
+    file.flush()?;
+
+    Ok(Layer {
+        digest,
+        diff_id,
+        size,
+    })
+}
+
+fn append_dir<W: Write>(
+    builder: &mut Builder<W>,
+    upperdir: &Path,
+    dir: &Path,
+    uid_mappings: &[IdMap],
+    gid_mappings: &[IdMap],
+) -> Result<()> {
+    let dir_path = upperdir.join(dir);
+
+    if !dir.as_os_str().is_empty() && is_opaque(&dir_path)? {
+        append_whiteout(builder, &dir.join(OPAQUE_WHITEOUT))?;
+    }
+
+    for entry in read_dir(&dir_path)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let path = dir.join(&name);
+        let metadata = entry.path().symlink_metadata()?;
+        let file_type = metadata.file_type();
+
+        // whiteout files created by fuse-overlayfs are already in OCI format
+        if name.as_bytes().starts_with(WHITEOUT_PREFIX.as_bytes()) {
+            if name != OPAQUE_WHITEOUT {
+                append_whiteout(builder, &path)?;
+            }
+            continue;
+        }
+
+        if is_whiteout(&metadata) {
+            let mut whiteout = WHITEOUT_PREFIX.as_bytes().to_vec();
+            whiteout.extend(name.as_bytes());
+            append_whiteout(builder, &dir.join(OsStr::from_bytes(&whiteout)))?;
+            continue;
+        }
+
+        let mut header = Header::new_gnu();
+        header.set_metadata(&metadata);
+        header.set_uid(
+            idmap::to_container(uid_mappings, metadata.uid()).unwrap_or(OVERFLOW_ID) as u64,
+        );
+        header.set_gid(
+            idmap::to_container(gid_mappings, metadata.gid()).unwrap_or(OVERFLOW_ID) as u64,
+        );
+
+        if file_type.is_dir() {
+            builder.append_data(&mut header, &path, io::empty())?;
+            append_dir(builder, upperdir, &path, uid_mappings, gid_mappings)?;
+        } else if file_type.is_symlink() {
+            let target = entry.path().read_link()?;
+            builder.append_link(&mut header, &path, target)?;
+        } else if file_type.is_file() {
+            builder.append_data(&mut header, &path, File::open(entry.path())?)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn append_whiteout<W: Write>(builder: &mut Builder<W>, path: &Path) -> Result<()> {
+    // unset numeric fields make readers reject the entry
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_uid(0);
+    header.set_gid(0);
+    header.set_mtime(0);
+    header.set_size(0);
+    builder.append_data(&mut header, path, io::empty())?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env,
+        fs::{create_dir_all, read, remove_dir_all, write},
+        process,
+    };
+
+    use flate2::read::GzDecoder;
+    use nix::unistd::{getgid, getuid};
+    use tar::Archive;
+
+    use super::*;
+
+    #[test]
+    fn whiteouts_are_written_in_oci_format() {
+        let dir = env::temp_dir().join(format!("con-layer-{}", process::id()));
+        let upper = dir.join("upper");
+        for file in [
+            "etc/.wh.passwd",
+            "etc/hosts",
+            "var/lib/.wh..wh..opq",
+            "var/lib/a",
+        ] {
+            let path = upper.join(file);
+            create_dir_all(path.parent().unwrap()).unwrap();
+            write(path, "").unwrap();
+        }
+        write(upper.join("etc/hosts"), "127.0.0.1 localhost\n").unwrap();
+
+        // files of the current user are owned by container's root
+        let uid_mappings = [IdMap {
+            container_id: 0,
+            host_id: getuid().as_raw(),
+            size: 1,
+        }];
+        let gid_mappings = [IdMap {
+            container_id: 0,
+            host_id: getgid().as_raw(),
+            size: 1,
+        }];
+        let path = dir.join("layer.tar.gz");
+        let layer = write_layer(&upper, &path, &uid_mappings, &gid_mappings).unwrap();
+
+        let content = read(&path).unwrap();
+        let mut entries = vec![];
+        for entry in Archive::new(GzDecoder::new(content.as_slice()))
+            .entries()
+            .unwrap()
+        {
+            let entry = entry.unwrap();
+            let header = entry.header();
+            entries.push(format!(
+                "{} {}:{} {}",
+                entry.path().unwrap().display(),
+                header.uid().unwrap(),
+                header.gid().unwrap(),
+                header.size().unwrap()
+            ));
+        }
+        entries.sort();
+        remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            entries,
+            [
+                "etc 0:0 0",
+                "etc/.wh.passwd 0:0 0",
+                "etc/hosts 0:0 20",
+                "var 0:0 0",
+                "var/lib 0:0 0",
+                "var/lib/.wh..wh..opq 0:0 0",
+                "var/lib/a 0:0 0",
+            ]
+        );
+        assert_eq!(layer.size, content.len() as u64);
+
+        let mut hasher = Sha256::new();
+        hasher.update(&content);
+        assert_eq!(layer.digest, format!("{:x}", hasher.finalize()));
+    }
+}
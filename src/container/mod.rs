@@ -1,5 +1,25 @@
+pub mod bpf;
+pub mod bridge;
 pub mod bundle;
 pub mod capabilities;
 pub mod cgroups;
+pub mod changes;
+pub mod devices;
 pub mod env;
+pub mod fs;
+pub mod hosts;
+pub mod idmap;
+pub mod idmapped;
+pub mod ipam;
+pub mod layer;
 pub mod namespaces;
+pub mod network;
+pub mod ports;
+pub mod proxy;
+pub mod resolv;
+pub mod security;
+pub mod slirp;
+pub mod spec;
+pub mod state;
+pub mod storage;
+pub mod user;
@@ -1,74 +1,384 @@
-use std::process::Command;
+use std::{os::unix::io::RawFd, path::Path, process::Command};
 
-use anyhow::Result;
+use anyhow::{anyhow, bail, Result};
 use nix::{
+    fcntl::{open, OFlag},
     libc::size_t,
     sched::{self, CloneFlags},
     sys::{
-        socket::{socketpair, AddressFamily, SockFlag, SockType},
-        wait,
+        socket::{
+            recvmsg, sendmsg, socketpair, AddressFamily, ControlMessage, ControlMessageOwned,
+            MsgFlags, SockFlag, SockType,
+        },
+        stat::Mode,
+        uio::IoVec,
+        wait::{self, WaitStatus},
     },
-    unistd::{self, getgid, getuid, Pid},
+    unistd::{self, close, getgid, getuid, pipe2, Pid, User},
 };
 
-pub fn run<F>(callback: F) -> Result<()>
+use crate::container::{
+    bridge,
+    idmap::{self, IdMap},
+    network::{self, Connection, CONTAINER_INTERFACE},
+    slirp::{self, Stack},
+};
+
+pub enum NetworkNamespace {
+    New(Connection),
+    Host,
+    /// Join user and network namespace of the process
+    Join(Pid),
+}
+
+pub struct Config {
+    pub uid_mappings: Vec<IdMap>,
+    pub gid_mappings: Vec<IdMap>,
+    pub network: NetworkNamespace,
+}
+
+impl Config {
+    /// Uses subordinate ids of the current user when no mappings are given.
+    pub fn new(
+        uid_mappings: Vec<IdMap>,
+        gid_mappings: Vec<IdMap>,
+        network: NetworkNamespace,
+    ) -> Result<Self> {
+        let uid = getuid();
+        let gid = getgid();
+        let name = User::from_uid(uid)?.map(|user| user.name);
+
+        let uid_mappings = if uid_mappings.is_empty() {
+            idmap::default_mappings(
+                Path::new("/etc/subuid"),
+                uid.as_raw(),
+                uid.as_raw(),
+                name.as_deref(),
+            )?
+        } else {
+            uid_mappings
+        };
+
+        let gid_mappings = if gid_mappings.is_empty() {
+            idmap::default_mappings(
+                Path::new("/etc/subgid"),
+                gid.as_raw(),
+                uid.as_raw(),
+                name.as_deref(),
+            )?
+        } else {
+            gid_mappings
+        };
+
+        Ok(Self {
+            uid_mappings,
+            gid_mappings,
+            network,
+        })
+    }
+}
+
+/// Runs `callback` in a child process inside new namespaces, the child exits
+/// with the code it returns. `setup_child` is called in the parent process
+/// with pid of the new pid namespace's init before it is allowed to continue.
+/// Returns how the child exited, or the error the child failed with.
+pub fn run<F, G>(config: &Config, callback: F, setup_child: G) -> Result<WaitStatus>
 where
-    F: Fn() -> Result<()>,
+    F: Fn() -> Result<i32>,
+    G: FnOnce(&Pid) -> Result<()>,
 {
     const STACK_SIZE: size_t = 1024 * 1024;
     let mut stack = [0u8; STACK_SIZE];
 
-    let flags = CloneFlags::CLONE_NEWNS
-        | CloneFlags::CLONE_NEWCGROUP
-        | CloneFlags::CLONE_NEWPID
-        | CloneFlags::CLONE_NEWIPC
-        | CloneFlags::CLONE_NEWNET
-        | CloneFlags::CLONE_NEWUTS
-        | CloneFlags::CLONE_NEWUSER;
+    let flags = match config.network {
+        NetworkNamespace::New(_) => {
+            unshared_flags() | CloneFlags::CLONE_NEWUSER | CloneFlags::CLONE_NEWNET
+        }
+        NetworkNamespace::Host => unshared_flags() | CloneFlags::CLONE_NEWUSER,
+        // user namespace of another container can be joined only from the
+        // initial one, so the rest of namespaces are unshared after joining
+        NetworkNamespace::Join(_) => CloneFlags::empty(),
+    };
 
+    // close-on-exec keeps the sockets out of the container's command
     let (socket1, socket2) = socketpair(
         AddressFamily::Unix,
         SockType::SeqPacket,
         None,
-        SockFlag::empty(),
+        SockFlag::SOCK_CLOEXEC,
     )?;
+    let (error_reader, error_writer) = pipe2(OFlag::O_CLOEXEC)?;
 
     let clone_callback = Box::new(|| {
-        let mut buf = [0u8; 4];
-        unistd::read(socket2, &mut buf).unwrap();
+        // parent's end held by the child would keep it waiting for a parent
+        // which has died
+        let result = close(socket1)
+            .map_err(anyhow::Error::from)
+            .and_then(|_| wait_for_parent(socket2))
+            .and_then(|_| match config.network {
+                NetworkNamespace::New(ref connection) => {
+                    setup_network(connection, socket2).and_then(|_| callback())
+                }
+                NetworkNamespace::Host => callback(),
+                NetworkNamespace::Join(pid) => {
+                    join(&pid).and_then(|_| run_in_new_pid_ns(&callback, socket2, error_writer))
+                }
+            });
+
+        exit_code_of(result, error_writer)
+    });
+
+    let child_pid = sched::clone(clone_callback, &mut stack, flags, None)?;
+    // child failing before it sends tap device makes recv_fd return EOF
+    close(socket2)?;
+    close(error_writer)?;
+
+    let stack = match prepare_child(config, &child_pid, socket1, setup_child) {
+        Ok(stack) => stack,
+        Err(err) => {
+            // child waiting for the parent fails once the socket is closed
+            close(socket1)?;
+            wait::waitpid(child_pid, Some(wait::WaitPidFlag::__WCLONE))?;
+            close(error_reader)?;
+            return Err(err);
+        }
+    };
 
-        if u32::from_le_bytes(buf) != 0 {
-            panic!("Socket error");
+    let status = wait::waitpid(child_pid, Some(wait::WaitPidFlag::__WCLONE))?;
+    close(socket1)?;
+    let error = read_error(error_reader);
+    close(error_reader)?;
+
+    if let Some(stack) = stack {
+        stack.stop()?;
+    }
+
+    match error? {
+        Some(error) => Err(anyhow!(error)),
+        None => Ok(status),
+    }
+}
+
+/// Exit code of a process with `status`, killed process exits with 128 plus
+/// the signal number like in shell.
+pub fn exit_code(status: WaitStatus) -> i32 {
+    match status {
+        WaitStatus::Exited(_, code) => code,
+        WaitStatus::Signaled(_, signal, _) => 128 + signal as i32,
+        _ => 1,
+    }
+}
+
+/// Exit code of a cloned child, its error is sent to the parent over
+/// `error_fd` including its causes.
+fn exit_code_of(result: Result<i32>, error_fd: RawFd) -> isize {
+    match result {
+        Ok(code) => code as isize,
+        Err(err) => {
+            let error = format!("{:#}", err);
+            if unistd::write(error_fd, error.as_bytes()).is_err() {
+                eprintln!("Error: {}", error);
+            }
+            1
         }
+    }
+}
+
+/// Reads error sent by the child until all its copies of the pipe are closed.
+fn read_error(fd: RawFd) -> Result<Option<String>> {
+    let mut error = vec![];
+    let mut buf = [0u8; 4096];
 
-        match callback() {
-            Ok(_) => return 0,
-            Err(err) => panic!("Error: {}", err.to_string()),
+    loop {
+        match unistd::read(fd, &mut buf)? {
+            0 => break,
+            len => error.extend_from_slice(&buf[..len]),
         }
-    });
+    }
 
-    let child_pid = sched::clone(clone_callback, &mut stack, flags, None)?;
+    if error.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(String::from_utf8_lossy(&error).into_owned()))
+}
+
+/// Sets up namespaces of the child and lets it continue. Returns userspace
+/// network stack of the child when it has one.
+fn prepare_child<G>(
+    config: &Config,
+    child_pid: &Pid,
+    socket: RawFd,
+    setup_child: G,
+) -> Result<Option<Stack>>
+where
+    G: FnOnce(&Pid) -> Result<()>,
+{
+    if !matches!(config.network, NetworkNamespace::Join(_)) {
+        configure_userns(child_pid, config)?;
+    }
+
+    if let NetworkNamespace::New(Connection::Veth(ref veth)) = config.network {
+        bridge::attach(veth, child_pid)?;
+    }
+
+    // child joining namespaces of another container sends pid of the init
+    // it starts after joining them
+    let init_pid = match config.network {
+        NetworkNamespace::Join(_) => {
+            unistd::write(socket, &0_i32.to_le_bytes())?;
+
+            let mut buf = [0u8; 4];
+            if unistd::read(socket, &mut buf)? != buf.len() {
+                bail!("Failed joining namespaces of the container");
+            }
+            Pid::from_raw(i32::from_le_bytes(buf))
+        }
+        _ => *child_pid,
+    };
+
+    setup_child(&init_pid)?;
+    unistd::write(socket, &0_i32.to_le_bytes())?;
+
+    Ok(match config.network {
+        NetworkNamespace::New(Connection::Userspace { host_loopback }) => {
+            Some(Stack::spawn(recv_fd(socket)?, host_loopback)?)
+        }
+        _ => None,
+    })
+}
+
+/// Blocks until the parent process allows the child to continue.
+fn wait_for_parent(socket: RawFd) -> Result<()> {
+    let mut buf = [0u8; 4];
+    if unistd::read(socket, &mut buf)? != buf.len() || u32::from_le_bytes(buf) != 0 {
+        bail!("Socket error");
+    }
+
+    Ok(())
+}
+
+fn unshared_flags() -> CloneFlags {
+    CloneFlags::CLONE_NEWNS
+        | CloneFlags::CLONE_NEWCGROUP
+        | CloneFlags::CLONE_NEWPID
+        | CloneFlags::CLONE_NEWIPC
+        | CloneFlags::CLONE_NEWUTS
+}
 
-    configure_userns(&child_pid)?;
-    unistd::write(socket1, &0_i32.to_le_bytes())?;
+fn setup_network(connection: &Connection, socket: RawFd) -> Result<()> {
+    network::setup_loopback()?;
 
-    wait::waitpid(child_pid, Some(wait::WaitPidFlag::__WCLONE))?;
+    match connection {
+        Connection::None => {}
+        Connection::Userspace { .. } => {
+            let tap_fd = slirp::create_tap()?;
+            send_fd(socket, tap_fd)?;
+            close(tap_fd)?;
+        }
+        Connection::Veth(veth) => {
+            network::rename_interface(&veth.peer_name, CONTAINER_INTERFACE)?;
+            network::configure_interface(
+                CONTAINER_INTERFACE,
+                veth.address,
+                veth.prefix_len,
+                veth.gateway,
+            )?;
+        }
+    }
 
     Ok(())
 }
 
-fn configure_userns(child_pid: &Pid) -> Result<()> {
-    let uid = getuid().as_raw().to_string();
-    Command::new("newuidmap")
-        .args([&child_pid.to_string(), "0", &uid, "1"])
-        .spawn()?
-        .wait()?;
+pub(crate) fn send_fd(socket: RawFd, fd: RawFd) -> Result<()> {
+    sendmsg(
+        socket,
+        &[IoVec::from_slice(&[0u8])],
+        &[ControlMessage::ScmRights(&[fd])],
+        MsgFlags::empty(),
+        None,
+    )?;
+
+    Ok(())
+}
+
+pub(crate) fn recv_fd(socket: RawFd) -> Result<RawFd> {
+    let mut buf = [0u8; 1];
+    let mut cmsg_buf = nix::cmsg_space!([RawFd; 1]);
+
+    let msg = recvmsg(
+        socket,
+        &[IoVec::from_mut_slice(&mut buf)],
+        Some(&mut cmsg_buf),
+        MsgFlags::empty(),
+    )?;
+
+    for cmsg in msg.cmsgs() {
+        if let ControlMessageOwned::ScmRights(fds) = cmsg {
+            if let Some(fd) = fds.first() {
+                return Ok(*fd);
+            }
+        }
+    }
+
+    Err(anyhow!("Container did not send file descriptor"))
+}
+
+fn join(pid: &Pid) -> Result<()> {
+    for (namespace, flag) in [
+        ("user", CloneFlags::CLONE_NEWUSER),
+        ("net", CloneFlags::CLONE_NEWNET),
+    ] {
+        let fd = open(
+            format!("/proc/{}/ns/{}", pid, namespace).as_str(),
+            OFlag::O_RDONLY,
+            Mode::empty(),
+        )?;
+        sched::setns(fd, flag)?;
+        close(fd)?;
+    }
+
+    sched::unshare(unshared_flags())?;
+
+    Ok(())
+}
+
+/// Unsharing pid namespace affects only children, so `callback` is run in a
+/// new child process which becomes init of the new pid namespace. Its pid is
+/// sent to the parent over `socket` before it waits for the parent. Returns
+/// exit code of the child, its error is sent over `error_fd`.
+fn run_in_new_pid_ns<F>(callback: &F, socket: RawFd, error_fd: RawFd) -> Result<i32>
+where
+    F: Fn() -> Result<i32>,
+{
+    let child =
+        Box::new(|| exit_code_of(wait_for_parent(socket).and_then(|_| callback()), error_fd));
+
+    let child_pid = sched::clone(child, &mut [0u8; 1024 * 1024], CloneFlags::empty(), None)?;
+    unistd::write(socket, &child_pid.as_raw().to_le_bytes())?;
+
+    let status = wait::waitpid(child_pid, Some(wait::WaitPidFlag::__WALL))?;
+
+    Ok(exit_code(status))
+}
 
-    let gid = getgid().as_raw().to_string();
-    Command::new("newgidmap")
-        .args([&child_pid.to_string(), "0", &gid, "1"])
+fn configure_userns(child_pid: &Pid, config: &Config) -> Result<()> {
+    write_mappings("newuidmap", child_pid, &config.uid_mappings)?;
+    write_mappings("newgidmap", child_pid, &config.gid_mappings)?;
+
+    Ok(())
+}
+
+fn write_mappings(program: &str, child_pid: &Pid, mappings: &[IdMap]) -> Result<()> {
+    let status = Command::new(program)
+        .arg(child_pid.to_string())
+        .args(mappings.iter().flat_map(|mapping| mapping.args()))
         .spawn()?
         .wait()?;
 
+    if !status.success() {
+        bail!("{} failed with {}", program, status);
+    }
+
     Ok(())
 }
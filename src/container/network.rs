@@ -0,0 +1,214 @@
+use std::{future::Future, net::Ipv4Addr, os::unix::io::RawFd, str::FromStr};
+
+use anyhow::{anyhow, Result};
+use futures::TryStreamExt;
+use nix::{
+    errno::Errno,
+    fcntl::{open, OFlag},
+    libc,
+    sys::stat::Mode,
+};
+use rtnetlink::Handle;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum NetworkMode {
+    /// New network namespace with only loopback interface
+    None,
+    /// New network namespace with userspace network stack providing
+    /// outbound connectivity, host's loopback is reachable through the
+    /// gateway only when it is allowed
+    Private { host_loopback: bool },
+    /// New network namespace attached to bridge of the named network (root
+    /// only)
+    Bridge(String),
+    /// Network namespace of the host
+    Host,
+    /// Network namespace of another container
+    Container(String),
+}
+
+impl FromStr for NetworkMode {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::None),
+            "private" => Ok(Self::Private {
+                host_loopback: false,
+            }),
+            "host" => Ok(Self::Host),
+            _ if s.starts_with("private:") => match &s["private:".len()..] {
+                "allow_host_loopback" | "allow_host_loopback=true" => Ok(Self::Private {
+                    host_loopback: true,
+                }),
+                "allow_host_loopback=false" => Ok(Self::Private {
+                    host_loopback: false,
+                }),
+                _ => Err("Invalid private network option. \
+                    Expected 'allow_host_loopback[=true|false]'"),
+            },
+            _ => match s.strip_prefix("container:") {
+                Some(container) if !container.is_empty() => {
+                    Ok(Self::Container(container.to_string()))
+                }
+                Some(_) => Err("Invalid network mode. Expected 'container:<name|id>'"),
+                None if !s.is_empty() && !s.contains('/') => Ok(Self::Bridge(s.to_string())),
+                None => Err("Invalid network mode. \
+                    Expected one of 'none', 'private', 'host', 'container:<name|id>' \
+                    or network name"),
+            },
+        }
+    }
+}
+
+/// Name of the veth peer inside the container.
+pub const CONTAINER_INTERFACE: &str = "eth0";
+
+/// How a new network namespace is connected to the outside.
+pub enum Connection {
+    /// Loopback interface only
+    None,
+    /// Tap device served by userspace network stack in con process
+    Userspace { host_loopback: bool },
+    /// Veth pair with host end attached to a bridge
+    Veth(Veth),
+}
+
+pub struct Veth {
+    pub host_name: String,
+    pub peer_name: String,
+    pub bridge: String,
+    pub address: Ipv4Addr,
+    pub prefix_len: u8,
+    pub gateway: Ipv4Addr,
+}
+
+impl Veth {
+    pub fn new(
+        container_id: &str,
+        bridge: String,
+        address: Ipv4Addr,
+        prefix_len: u8,
+        gateway: Ipv4Addr,
+    ) -> Self {
+        Self {
+            host_name: format!("veth{}", &container_id[..8]),
+            peer_name: format!("ceth{}", &container_id[..8]),
+            bridge,
+            address,
+            prefix_len,
+            gateway,
+        }
+    }
+}
+
+pub(crate) fn block_on<F, T>(future: F) -> Result<T>
+where
+    F: Future<Output = Result<T>>,
+{
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?
+        .block_on(future)
+}
+
+pub(crate) async fn connect() -> Result<Handle> {
+    let (connection, handle, _) = rtnetlink::new_connection()?;
+    tokio::spawn(connection);
+
+    Ok(handle)
+}
+
+pub(crate) async fn link_index(handle: &Handle, name: &str) -> Result<u32> {
+    let mut links = handle.link().get().match_name(name.to_string()).execute();
+
+    match links.try_next().await? {
+        Some(link) => Ok(link.header.index),
+        None => Err(anyhow!("Network interface {} not found", name)),
+    }
+}
+
+/// Brings up loopback interface in the current network namespace.
+pub fn setup_loopback() -> Result<()> {
+    block_on(async {
+        let handle = connect().await?;
+        let index = link_index(&handle, "lo").await?;
+        handle.link().set(index).up().execute().await?;
+
+        Ok(())
+    })
+}
+
+/// Brings up interface `name`, assigns it `address` and routes all traffic
+/// through `gateway`.
+pub fn configure_interface(
+    name: &str,
+    address: Ipv4Addr,
+    prefix_len: u8,
+    gateway: Ipv4Addr,
+) -> Result<()> {
+    block_on(async {
+        let handle = connect().await?;
+        let index = link_index(&handle, name).await?;
+
+        handle
+            .address()
+            .add(index, address.into(), prefix_len)
+            .execute()
+            .await?;
+        handle.link().set(index).up().execute().await?;
+        handle.route().add().v4().gateway(gateway).execute().await?;
+
+        Ok(())
+    })
+}
+
+/// Renames interface `name` to `new_name` in the current network namespace.
+pub fn rename_interface(name: &str, new_name: &str) -> Result<()> {
+    block_on(async {
+        let handle = connect().await?;
+        let index = link_index(&handle, name).await?;
+        handle
+            .link()
+            .set(index)
+            .name(new_name.to_string())
+            .execute()
+            .await?;
+
+        Ok(())
+    })
+}
+
+const TUNSETIFF: libc::c_ulong = 0x400454ca;
+const IFF_TAP: libc::c_short = 0x0002;
+const IFF_NO_PI: libc::c_short = 0x1000;
+
+#[repr(C)]
+struct IfReq {
+    name: [u8; libc::IFNAMSIZ],
+    flags: libc::c_short,
+    _pad: [u8; 22],
+}
+
+/// Creates tap device `name` in the current network namespace. Device exists
+/// as long as the returned file descriptor is open.
+pub fn create_tap(name: &str) -> Result<RawFd> {
+    let fd = open(
+        "/dev/net/tun",
+        OFlag::O_RDWR | OFlag::O_CLOEXEC,
+        Mode::empty(),
+    )?;
+
+    let mut req = IfReq {
+        name: [0; libc::IFNAMSIZ],
+        flags: IFF_TAP | IFF_NO_PI,
+        _pad: [0; 22],
+    };
+    req.name[..name.len()].copy_from_slice(name.as_bytes());
+
+    let res = unsafe { libc::ioctl(fd, TUNSETIFF, &req) };
+    Errno::result(res)?;
+
+    Ok(fd)
+}
@@ -0,0 +1,191 @@
+use std::{
+    fmt,
+    net::{Ipv4Addr, SocketAddr, TcpListener, UdpSocket},
+    str::FromStr,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    Tcp,
+    Udp,
+}
+
+impl FromStr for Protocol {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(Self::Tcp),
+            "udp" => Ok(Self::Udp),
+            _ => Err("Invalid protocol. Expected 'tcp' or 'udp'"),
+        }
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp => write!(f, "tcp"),
+            Self::Udp => write!(f, "udp"),
+        }
+    }
+}
+
+/// Container port published on the host. Host port 0 stands for a free port
+/// picked when the mapping is bound.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PortMapping {
+    pub host_ip: Ipv4Addr,
+    pub host_port: u16,
+    pub container_port: u16,
+    pub protocol: Protocol,
+}
+
+impl FromStr for PortMapping {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (ports, protocol) = match s.split_once("/") {
+            Some((ports, protocol)) => (ports, Protocol::from_str(protocol)?),
+            None => (s, Protocol::Tcp),
+        };
+
+        let parts: Vec<&str> = ports.split(':').collect();
+        let (host_ip, host_port, container_port) = match parts.as_slice() {
+            [container_port] => ("", "", *container_port),
+            [host_port, container_port] => ("", *host_port, *container_port),
+            [host_ip, host_port, container_port] => (*host_ip, *host_port, *container_port),
+            _ => {
+                return Err("Invalid port syntax. \
+                    Expected in format '[host_ip:][host_port:]container_port[/protocol]'")
+            }
+        };
+
+        let host_ip = match host_ip {
+            "" => Ipv4Addr::UNSPECIFIED,
+            host_ip => match host_ip.parse() {
+                Ok(host_ip) => host_ip,
+                Err(_) => return Err("Invalid host IP address"),
+            },
+        };
+
+        let host_port = match host_port {
+            "" => 0,
+            host_port => match host_port.parse() {
+                Ok(host_port) => host_port,
+                Err(_) => return Err("Invalid host port"),
+            },
+        };
+
+        let container_port = match container_port.parse() {
+            Ok(container_port) if container_port != 0 => container_port,
+            _ => return Err("Invalid container port"),
+        };
+
+        Ok(Self {
+            host_ip,
+            host_port,
+            container_port,
+            protocol,
+        })
+    }
+}
+
+impl fmt::Display for PortMapping {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} -> {}:{}",
+            self.container_port, self.protocol, self.host_ip, self.host_port
+        )
+    }
+}
+
+impl PortMapping {
+    /// Mapping of image's exposed port (e.g. `80/tcp`) to a free host port.
+    pub fn exposed(port: &str) -> Result<Self, &'static str> {
+        let (port, protocol) = match port.split_once("/") {
+            Some((port, protocol)) => (port, Protocol::from_str(protocol)?),
+            None => (port, Protocol::Tcp),
+        };
+
+        match port.parse() {
+            Ok(container_port) => Ok(Self {
+                host_ip: Ipv4Addr::UNSPECIFIED,
+                host_port: 0,
+                container_port,
+                protocol,
+            }),
+            Err(_) => Err("Invalid exposed port"),
+        }
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Udp(UdpSocket),
+}
+
+/// Binds host side of the mappings, replacing port 0 with the port picked by
+/// the kernel.
+pub fn bind(mappings: &mut [PortMapping]) -> Result<Vec<Listener>> {
+    let mut listeners = vec![];
+
+    for mapping in mappings.iter_mut() {
+        let address = SocketAddr::from((mapping.host_ip, mapping.host_port));
+
+        let (listener, local_address) = match mapping.protocol {
+            Protocol::Tcp => {
+                let listener = TcpListener::bind(address)?;
+                let local_address = listener.local_addr()?;
+                (Listener::Tcp(listener), local_address)
+            }
+            Protocol::Udp => {
+                let socket = UdpSocket::bind(address)?;
+                let local_address = socket.local_addr()?;
+                (Listener::Udp(socket), local_address)
+            }
+        };
+
+        mapping.host_port = local_address.port();
+        listeners.push(listener);
+    }
+
+    Ok(listeners)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_port_mapping() {
+        let cases = [
+            ("80", Ok("80/tcp -> 0.0.0.0:0")),
+            ("8080:80", Ok("80/tcp -> 0.0.0.0:8080")),
+            ("127.0.0.1:8080:80/udp", Ok("80/udp -> 127.0.0.1:8080")),
+            ("127.0.0.1::80", Ok("80/tcp -> 127.0.0.1:0")),
+            ("65535:65535", Ok("65535/tcp -> 0.0.0.0:65535")),
+            ("0", Err("Invalid container port")),
+            ("65536", Err("Invalid container port")),
+            ("", Err("Invalid container port")),
+            ("80/sctp", Err("Invalid protocol. Expected 'tcp' or 'udp'")),
+            ("http:80", Err("Invalid host port")),
+            ("localhost:8080:80", Err("Invalid host IP address")),
+            (
+                "::1:8080:80",
+                Err("Invalid port syntax. \
+                Expected in format '[host_ip:][host_port:]container_port[/protocol]'"),
+            ),
+        ];
+
+        for (input, expected) in cases {
+            let mapping = PortMapping::from_str(input).map(|mapping| mapping.to_string());
+            assert_eq!(mapping, expected.map(String::from), "{}", input);
+        }
+    }
+}
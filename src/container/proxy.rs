@@ -0,0 +1,324 @@
+use std::{
+    collections::HashMap,
+    io::{copy, ErrorKind},
+    net::{Ipv4Addr, Shutdown, SocketAddr, TcpStream, UdpSocket},
+    os::unix::io::{AsRawFd, FromRawFd, RawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use nix::{
+    errno::Errno,
+    fcntl::{open, OFlag},
+    poll::{poll, PollFd, PollFlags},
+    sched::{self, CloneFlags},
+    sys::{
+        signal::Signal,
+        socket::{self, socketpair, AddressFamily, InetAddr, SockAddr, SockFlag, SockType},
+        stat::Mode,
+        wait::waitpid,
+    },
+    unistd::{self, close, Pid},
+};
+
+use crate::container::{
+    namespaces::{recv_fd, send_fd},
+    ports::{Listener, PortMapping, Protocol},
+};
+
+const UDP_FLOW_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_TIMEOUT_MS: i32 = 50;
+
+/// Process living in container's user and network namespace which creates
+/// sockets on request. Sockets stay in the namespace they were created in, so
+/// the proxy can connect them to container's loopback.
+struct SocketFactory {
+    socket: Mutex<RawFd>,
+    pid: Pid,
+}
+
+impl SocketFactory {
+    fn spawn(container_pid: &Pid) -> Result<Self> {
+        let (socket, child_socket) = socketpair(
+            AddressFamily::Unix,
+            SockType::SeqPacket,
+            None,
+            SockFlag::SOCK_CLOEXEC,
+        )?;
+
+        let container_pid = *container_pid;
+        let callback = Box::new(|| match serve(&container_pid, child_socket) {
+            Ok(_) => 0,
+            Err(err) => panic!("Error: {}", err),
+        });
+
+        let pid = sched::clone(
+            callback,
+            &mut [0u8; 64 * 1024],
+            CloneFlags::empty(),
+            Some(Signal::SIGCHLD as i32),
+        )?;
+        close(child_socket)?;
+
+        Ok(Self {
+            socket: Mutex::new(socket),
+            pid,
+        })
+    }
+
+    fn connect(&self, protocol: Protocol, port: u16) -> Result<RawFd> {
+        let fd = {
+            let socket = self.socket.lock().unwrap();
+            unistd::write(*socket, &[protocol as u8])?;
+            recv_fd(*socket)?
+        };
+
+        let address = SocketAddr::from((Ipv4Addr::LOCALHOST, port));
+        if let Err(err) = socket::connect(fd, &SockAddr::new_inet(InetAddr::from_std(&address))) {
+            close(fd)?;
+            return Err(err.into());
+        }
+
+        Ok(fd)
+    }
+}
+
+impl Drop for SocketFactory {
+    fn drop(&mut self) {
+        let _ = close(*self.socket.lock().unwrap());
+        let _ = waitpid(self.pid, None);
+    }
+}
+
+fn serve(container_pid: &Pid, socket: RawFd) -> Result<()> {
+    for (namespace, flag) in [
+        ("user", CloneFlags::CLONE_NEWUSER),
+        ("net", CloneFlags::CLONE_NEWNET),
+    ] {
+        let fd = open(
+            format!("/proc/{}/ns/{}", container_pid, namespace).as_str(),
+            OFlag::O_RDONLY,
+            Mode::empty(),
+        )?;
+        sched::setns(fd, flag)?;
+        close(fd)?;
+    }
+
+    let mut buf = [0u8; 1];
+    while unistd::read(socket, &mut buf)? != 0 {
+        let socket_type = if buf[0] == Protocol::Udp as u8 {
+            SockType::Datagram
+        } else {
+            SockType::Stream
+        };
+
+        let fd = socket::socket(
+            AddressFamily::Inet,
+            socket_type,
+            SockFlag::SOCK_CLOEXEC,
+            None,
+        )?;
+        send_fd(socket, fd)?;
+        close(fd)?;
+    }
+
+    Ok(())
+}
+
+struct UdpFlow {
+    socket: UdpSocket,
+    last_used: Instant,
+}
+
+/// Userspace proxy forwarding connections to published host ports into
+/// container's network namespace.
+pub struct Proxy {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl Proxy {
+    pub fn spawn(
+        container_pid: &Pid,
+        mappings: Vec<PortMapping>,
+        listeners: Vec<Listener>,
+    ) -> Result<Self> {
+        let factory = Arc::new(SocketFactory::spawn(container_pid)?);
+
+        for listener in &listeners {
+            match listener {
+                Listener::Tcp(listener) => listener.set_nonblocking(true)?,
+                Listener::Udp(socket) => socket.set_nonblocking(true)?,
+            }
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+
+        let thread = thread::spawn(move || {
+            let mut proxy = ProxyLoop {
+                factory,
+                mappings,
+                listeners,
+                udp_flows: HashMap::new(),
+            };
+
+            while !thread_stop.load(Ordering::Relaxed) {
+                proxy.wait()?;
+                proxy.accept_tcp();
+                proxy.pump_udp()?;
+            }
+
+            Ok(())
+        });
+
+        Ok(Self { stop, thread })
+    }
+
+    pub fn stop(self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!("Port proxy thread panicked")),
+        }
+    }
+}
+
+struct ProxyLoop {
+    factory: Arc<SocketFactory>,
+    mappings: Vec<PortMapping>,
+    listeners: Vec<Listener>,
+    /// Flows keyed by index of the listener and client address
+    udp_flows: HashMap<(usize, SocketAddr), UdpFlow>,
+}
+
+impl ProxyLoop {
+    fn wait(&self) -> Result<()> {
+        let mut fds = self
+            .listeners
+            .iter()
+            .map(|listener| match listener {
+                Listener::Tcp(listener) => listener.as_raw_fd(),
+                Listener::Udp(socket) => socket.as_raw_fd(),
+            })
+            .chain(self.udp_flows.values().map(|flow| flow.socket.as_raw_fd()))
+            .map(|fd| PollFd::new(fd, PollFlags::POLLIN))
+            .collect::<Vec<_>>();
+
+        match poll(&mut fds, POLL_TIMEOUT_MS) {
+            Ok(_) | Err(Errno::EINTR) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn accept_tcp(&self) {
+        for (mapping, listener) in self.mappings.iter().zip(&self.listeners) {
+            let listener = match listener {
+                Listener::Tcp(listener) => listener,
+                Listener::Udp(_) => continue,
+            };
+
+            while let Ok((client, _)) = listener.accept() {
+                let factory = self.factory.clone();
+                let port = mapping.container_port;
+
+                thread::spawn(move || {
+                    if let Ok(fd) = factory.connect(Protocol::Tcp, port) {
+                        let container = unsafe { TcpStream::from_raw_fd(fd) };
+                        let _ = forward_tcp(client, container);
+                    }
+                });
+            }
+        }
+    }
+
+    fn pump_udp(&mut self) -> Result<()> {
+        let mut buf = vec![0u8; 65536];
+
+        for (index, listener) in self.listeners.iter().enumerate() {
+            let socket = match listener {
+                Listener::Udp(socket) => socket,
+                Listener::Tcp(_) => continue,
+            };
+
+            // host -> container
+            loop {
+                let (len, client) = match socket.recv_from(&mut buf) {
+                    Ok(received) => received,
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => return Err(err.into()),
+                };
+
+                let key = (index, client);
+                if !self.udp_flows.contains_key(&key) {
+                    let port = self.mappings[index].container_port;
+                    let fd = match self.factory.connect(Protocol::Udp, port) {
+                        Ok(fd) => fd,
+                        Err(_) => continue,
+                    };
+
+                    let flow_socket = unsafe { UdpSocket::from_raw_fd(fd) };
+                    flow_socket.set_nonblocking(true)?;
+                    self.udp_flows.insert(
+                        key,
+                        UdpFlow {
+                            socket: flow_socket,
+                            last_used: Instant::now(),
+                        },
+                    );
+                }
+
+                if let Some(flow) = self.udp_flows.get_mut(&key) {
+                    let _ = flow.socket.send(&buf[..len]);
+                    flow.last_used = Instant::now();
+                }
+            }
+        }
+
+        // container -> host
+        for ((index, client), flow) in self.udp_flows.iter_mut() {
+            let socket = match &self.listeners[*index] {
+                Listener::Udp(socket) => socket,
+                Listener::Tcp(_) => continue,
+            };
+
+            while let Ok(len) = flow.socket.recv(&mut buf) {
+                let _ = socket.send_to(&buf[..len], client);
+                flow.last_used = Instant::now();
+            }
+        }
+
+        self.udp_flows
+            .retain(|_, flow| flow.last_used.elapsed() < UDP_FLOW_TIMEOUT);
+
+        Ok(())
+    }
+}
+
+/// Copies data in both directions until both sides close their write half.
+fn forward_tcp(client: TcpStream, container: TcpStream) -> Result<()> {
+    client.set_nonblocking(false)?;
+
+    let mut client_reader = client.try_clone()?;
+    let mut container_writer = container.try_clone()?;
+
+    let upstream = thread::spawn(move || {
+        let _ = copy(&mut client_reader, &mut container_writer);
+        let _ = container_writer.shutdown(Shutdown::Write);
+    });
+
+    let mut container_reader = container;
+    let mut client_writer = client;
+    let _ = copy(&mut container_reader, &mut client_writer);
+    let _ = client_writer.shutdown(Shutdown::Write);
+
+    let _ = upstream.join();
+
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::{
+    fs::{read_to_string, write},
+    net::{IpAddr, Ipv4Addr},
+    path::PathBuf,
+};
+
+use anyhow::Result;
+
+use crate::container::state::State;
+
+/// Used when none of host's name servers is reachable from the container
+const DEFAULT_NAMESERVERS: [IpAddr; 2] = [
+    IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8)),
+    IpAddr::V4(Ipv4Addr::new(8, 8, 4, 4)),
+];
+
+/// Resolver configuration written to container's /etc/resolv.conf.
+#[derive(Debug, Default)]
+pub struct ResolvConf {
+    pub nameservers: Vec<IpAddr>,
+    pub search: Vec<String>,
+    pub options: Vec<String>,
+}
+
+impl ResolvConf {
+    /// Configuration of the host, empty if host has no resolv.conf.
+    pub fn host() -> Result<Self> {
+        let content = read_to_string("/etc/resolv.conf").unwrap_or_default();
+        let mut config = Self::default();
+
+        for line in content.lines() {
+            let mut fields = line.split_whitespace();
+
+            match fields.next() {
+                Some("nameserver") => {
+                    if let Some(Ok(address)) = fields.next().map(str::parse) {
+                        config.nameservers.push(address);
+                    }
+                }
+                Some("search") | Some("domain") => {
+                    config.search = fields.map(String::from).collect();
+                }
+                Some("options") => config.options.extend(fields.map(String::from)),
+                _ => {}
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Drops name servers listening on host's loopback, which is not reachable
+    /// from container's network namespace.
+    pub fn without_loopback(mut self) -> Self {
+        self.nameservers.retain(|address| !address.is_loopback());
+
+        if self.nameservers.is_empty() {
+            self.nameservers = DEFAULT_NAMESERVERS.to_vec();
+        }
+
+        self
+    }
+
+    pub fn write(&self, state: &State) -> Result<()> {
+        let mut content = String::new();
+
+        for address in &self.nameservers {
+            content.push_str(&format!("nameserver {}\n", address));
+        }
+
+        if !self.search.is_empty() {
+            content.push_str(&format!("search {}\n", self.search.join(" ")));
+        }
+
+        if !self.options.is_empty() {
+            content.push_str(&format!("options {}\n", self.options.join(" ")));
+        }
+
+        write(resolv_path(state)?, content)?;
+
+        Ok(())
+    }
+}
+
+/// Generated resolv.conf bind mounted to container's /etc/resolv.conf.
+pub fn resolv_path(state: &State) -> Result<PathBuf> {
+    Ok(state.dir()?.join("resolv.conf"))
+}
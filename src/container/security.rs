@@ -0,0 +1,177 @@
+use std::{path::PathBuf, str::FromStr};
+
+use anyhow::Result;
+use nix::{errno::Errno, libc};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_MASKED_PATHS: [&str; 11] = [
+    "/proc/acpi",
+    "/proc/asound",
+    "/proc/kcore",
+    "/proc/keys",
+    "/proc/latency_stats",
+    "/proc/sched_debug",
+    "/proc/scsi",
+    "/proc/timer_list",
+    "/proc/timer_stats",
+    "/sys/devices/virtual/powercap",
+    "/sys/firmware",
+];
+
+const DEFAULT_READONLY_PATHS: [&str; 6] = [
+    "/proc/bus",
+    "/proc/fs",
+    "/proc/irq",
+    "/proc/sys",
+    "/proc/sysrq-trigger",
+    "/sys",
+];
+
+#[derive(Debug)]
+pub enum SecurityOpt {
+    NoNewPrivileges(bool),
+    SystemPathsUnconfined,
+    Mask(Vec<PathBuf>),
+    Unmask(Vec<PathBuf>),
+    UnmaskAll,
+    ReadOnly(Vec<PathBuf>),
+}
+
+fn parse_paths(s: &str) -> Result<Vec<PathBuf>, &'static str> {
+    let paths: Vec<PathBuf> = s
+        .split(":")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+        .collect();
+
+    if paths.is_empty() || paths.iter().any(|path| !path.is_absolute()) {
+        return Err("Expected colon separated list of absolute paths");
+    }
+
+    Ok(paths)
+}
+
+fn parse_bool(s: &str) -> Result<bool, &'static str> {
+    match s {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err("Expected 'true' or 'false'"),
+    }
+}
+
+impl FromStr for SecurityOpt {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = match s.split_once(|c| c == '=' || c == ':') {
+            Some((key, value)) => (key, Some(value)),
+            None => (s, None),
+        };
+
+        match (key, value) {
+            ("no-new-privileges", None) => Ok(Self::NoNewPrivileges(true)),
+            ("no-new-privileges", Some(value)) => Ok(Self::NoNewPrivileges(parse_bool(value)?)),
+            ("systempaths", Some("unconfined")) => Ok(Self::SystemPathsUnconfined),
+            ("mask", Some(value)) => Ok(Self::Mask(parse_paths(value)?)),
+            ("unmask", Some("ALL")) => Ok(Self::UnmaskAll),
+            ("unmask", Some(value)) => Ok(Self::Unmask(parse_paths(value)?)),
+            ("readonly", Some(value)) => Ok(Self::ReadOnly(parse_paths(value)?)),
+            _ => Err(
+                "Invalid security option. Expected one of 'no-new-privileges[=true|false]', \
+                'systempaths=unconfined', 'mask=path[:path]', 'unmask=ALL|path[:path]', \
+                'readonly=path[:path]'",
+            ),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Config {
+    pub no_new_privileges: bool,
+    pub masked_paths: Vec<PathBuf>,
+    pub readonly_paths: Vec<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            no_new_privileges: true,
+            masked_paths: DEFAULT_MASKED_PATHS.iter().map(PathBuf::from).collect(),
+            readonly_paths: DEFAULT_READONLY_PATHS.iter().map(PathBuf::from).collect(),
+        }
+    }
+}
+
+impl Config {
+    pub fn new<'a, I>(opts: I) -> Self
+    where
+        I: Iterator<Item = &'a SecurityOpt>,
+    {
+        let mut config = Self::default();
+
+        for opt in opts {
+            match opt {
+                SecurityOpt::NoNewPrivileges(value) => config.no_new_privileges = *value,
+                SecurityOpt::SystemPathsUnconfined | SecurityOpt::UnmaskAll => {
+                    config.masked_paths.clear();
+                    config.readonly_paths.clear();
+                }
+                SecurityOpt::Mask(paths) => config.masked_paths.extend(paths.iter().cloned()),
+                SecurityOpt::Unmask(paths) => {
+                    config.masked_paths.retain(|path| !paths.contains(path));
+                    config.readonly_paths.retain(|path| !paths.contains(path));
+                }
+                SecurityOpt::ReadOnly(paths) => config.readonly_paths.extend(paths.iter().cloned()),
+            }
+        }
+
+        config
+    }
+}
+
+pub fn set_no_new_privs() -> Result<()> {
+    let res = unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) };
+    Errno::result(res)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVALID_OPTION: &str = "Invalid security option. Expected one of \
+        'no-new-privileges[=true|false]', 'systempaths=unconfined', \
+        'mask=path[:path]', 'unmask=ALL|path[:path]', 'readonly=path[:path]'";
+    const INVALID_PATHS: &str = "Expected colon separated list of absolute paths";
+
+    #[test]
+    fn parse_security_opt() {
+        let cases = [
+            ("no-new-privileges", Ok("NoNewPrivileges(true)")),
+            ("no-new-privileges=false", Ok("NoNewPrivileges(false)")),
+            ("no-new-privileges:true", Ok("NoNewPrivileges(true)")),
+            ("systempaths=unconfined", Ok("SystemPathsUnconfined")),
+            (
+                "mask=/proc/acpi:/sys/firmware",
+                Ok("Mask([\"/proc/acpi\", \"/sys/firmware\"])"),
+            ),
+            ("unmask=ALL", Ok("UnmaskAll")),
+            ("unmask=/proc/kcore:", Ok("Unmask([\"/proc/kcore\"])")),
+            ("readonly=/proc/sys", Ok("ReadOnly([\"/proc/sys\"])")),
+            ("no-new-privileges=yes", Err("Expected 'true' or 'false'")),
+            ("mask=proc/acpi", Err(INVALID_PATHS)),
+            ("readonly=", Err(INVALID_PATHS)),
+            ("unmask=/proc/kcore:relative", Err(INVALID_PATHS)),
+            ("systempaths=confined", Err(INVALID_OPTION)),
+            ("mask", Err(INVALID_OPTION)),
+            ("seccomp=unconfined", Err(INVALID_OPTION)),
+            ("", Err(INVALID_OPTION)),
+        ];
+
+        for (input, expected) in cases {
+            let opt = SecurityOpt::from_str(input).map(|opt| format!("{:?}", opt));
+            assert_eq!(opt, expected.map(String::from), "{}", input);
+        }
+    }
+}
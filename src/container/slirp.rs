@@ -0,0 +1,725 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fs::read_to_string,
+    io::{ErrorKind, Read, Write},
+    net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpStream, UdpSocket},
+    os::unix::io::{AsRawFd, RawFd},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, TryRecvError},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use nix::{
+    errno::Errno,
+    fcntl::{fcntl, FcntlArg, OFlag},
+    poll::{poll, PollFd, PollFlags},
+    unistd,
+};
+use smoltcp::{
+    iface::{Config, Interface, SocketHandle, SocketSet},
+    phy::{self, ChecksumCapabilities, Device, DeviceCapabilities, Medium},
+    socket::tcp,
+    time::Instant,
+    wire::{
+        EthernetAddress, EthernetFrame, EthernetProtocol, EthernetRepr, IpAddress, IpCidr,
+        IpListenEndpoint, IpProtocol, Ipv4Address, Ipv4Packet, Ipv4Repr, TcpPacket, UdpPacket,
+        UdpRepr,
+    },
+};
+
+use crate::container::network;
+
+pub const TAP_NAME: &str = "tap0";
+pub const CONTAINER_ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 100);
+/// Gateway address, connections to it are forwarded to host's loopback when
+/// it is allowed
+pub const GATEWAY_ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 2);
+/// DNS server address, queries are forwarded to host's name server
+pub const DNS_ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 0, 2, 3);
+const PREFIX_LEN: u8 = 24;
+
+const MAC_ADDRESS: EthernetAddress = EthernetAddress([0x52, 0x55, 0x0a, 0x00, 0x02, 0x02]);
+const MTU: usize = 1500;
+const TCP_BUFFER_SIZE: usize = 64 * 1024;
+const UDP_FLOW_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_TIMEOUT_MS: i32 = 50;
+
+/// Creates and configures tap device inside of container's network namespace.
+/// Returned file descriptor should be handed to [`Stack`] running on the host.
+pub fn create_tap() -> Result<RawFd> {
+    let fd = network::create_tap(TAP_NAME)?;
+    network::configure_interface(TAP_NAME, CONTAINER_ADDRESS, PREFIX_LEN, GATEWAY_ADDRESS)?;
+
+    Ok(fd)
+}
+
+struct TapDevice {
+    fd: RawFd,
+    rx_queue: VecDeque<Vec<u8>>,
+}
+
+struct RxToken(Vec<u8>);
+
+struct TxToken(RawFd);
+
+impl phy::RxToken for RxToken {
+    fn consume<R, F>(mut self, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        f(&mut self.0)
+    }
+}
+
+impl phy::TxToken for TxToken {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buf = vec![0u8; len];
+        let result = f(&mut buf);
+        // dropped frames are retransmitted by TCP
+        let _ = unistd::write(self.0, &buf);
+
+        result
+    }
+}
+
+impl Device for TapDevice {
+    type RxToken<'a>
+        = RxToken
+    where
+        Self: 'a;
+    type TxToken<'a>
+        = TxToken
+    where
+        Self: 'a;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(Self::RxToken<'_>, Self::TxToken<'_>)> {
+        let fd = self.fd;
+        self.rx_queue
+            .pop_front()
+            .map(|frame| (RxToken(frame), TxToken(fd)))
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<Self::TxToken<'_>> {
+        Some(TxToken(self.fd))
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut capabilities = DeviceCapabilities::default();
+        capabilities.medium = Medium::Ethernet;
+        capabilities.max_transmission_unit = MTU + EthernetFrame::<&[u8]>::header_len();
+
+        capabilities
+    }
+}
+
+type FlowKey = (Ipv4Address, u16, Ipv4Address, u16);
+
+struct TcpConnection {
+    handle: SocketHandle,
+    connecting: Option<Receiver<std::io::Result<TcpStream>>>,
+    stream: Option<TcpStream>,
+    /// Data received from container not yet written to host
+    pending: Vec<u8>,
+    established: bool,
+    host_closed: bool,
+    container_closed: bool,
+}
+
+struct UdpFlow {
+    socket: UdpSocket,
+    container_port: u16,
+    remote_address: Ipv4Address,
+    remote_port: u16,
+    last_used: std::time::Instant,
+}
+
+/// Userspace network stack translating container's traffic on tap device to
+/// host sockets.
+pub struct Stack {
+    stop: Arc<AtomicBool>,
+    thread: JoinHandle<Result<()>>,
+}
+
+impl Stack {
+    /// Connections to the gateway are forwarded to host's loopback only with
+    /// `host_loopback`, otherwise they are refused.
+    pub fn spawn(tap_fd: RawFd, host_loopback: bool) -> Result<Self> {
+        fcntl(tap_fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK))?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread_stop = stop.clone();
+        let dns_server = host_dns_server()?;
+
+        let thread = thread::spawn(move || {
+            let result = StackLoop::new(tap_fd, dns_server, host_loopback).run(&thread_stop);
+            unistd::close(tap_fd)?;
+
+            result
+        });
+
+        Ok(Self { stop, thread })
+    }
+
+    pub fn stop(self) -> Result<()> {
+        self.stop.store(true, Ordering::Relaxed);
+
+        match self.thread.join() {
+            Ok(result) => result,
+            Err(_) => Err(anyhow!("Network stack thread panicked")),
+        }
+    }
+}
+
+fn host_dns_server() -> Result<Ipv4Addr> {
+    let content = read_to_string("/etc/resolv.conf").unwrap_or_default();
+
+    Ok(content
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|address| address.trim().parse().ok())
+        .next()
+        .unwrap_or(Ipv4Addr::new(8, 8, 8, 8)))
+}
+
+struct StackLoop {
+    device: TapDevice,
+    iface: Interface,
+    sockets: SocketSet<'static>,
+    tcp_connections: HashMap<FlowKey, TcpConnection>,
+    udp_flows: HashMap<FlowKey, UdpFlow>,
+    container_mac: Option<EthernetAddress>,
+    dns_server: Ipv4Addr,
+    host_loopback: bool,
+}
+
+impl StackLoop {
+    fn new(tap_fd: RawFd, dns_server: Ipv4Addr, host_loopback: bool) -> Self {
+        let mut device = TapDevice {
+            fd: tap_fd,
+            rx_queue: VecDeque::new(),
+        };
+
+        let mut iface = Interface::new(Config::new(MAC_ADDRESS.into()), &mut device, now());
+        iface.update_ip_addrs(|addrs| {
+            for address in [GATEWAY_ADDRESS, DNS_ADDRESS] {
+                let _ = addrs.push(IpCidr::new(Ipv4Address::from(address).into(), PREFIX_LEN));
+            }
+        });
+        // accept packets for any destination, they are routed through us
+        iface
+            .routes_mut()
+            .add_default_ipv4_route(GATEWAY_ADDRESS.into())
+            .unwrap();
+        iface.set_any_ip(true);
+
+        Self {
+            device,
+            iface,
+            sockets: SocketSet::new(vec![]),
+            tcp_connections: HashMap::new(),
+            udp_flows: HashMap::new(),
+            container_mac: None,
+            dns_server,
+            host_loopback,
+        }
+    }
+
+    fn run(&mut self, stop: &AtomicBool) -> Result<()> {
+        let mut buf = vec![0u8; 65536];
+
+        while !stop.load(Ordering::Relaxed) {
+            loop {
+                match unistd::read(self.device.fd, &mut buf) {
+                    Ok(0) => return Ok(()),
+                    Ok(len) => self.handle_frame(&buf[..len]),
+                    Err(Errno::EAGAIN) => break,
+                    // container's network namespace is gone
+                    Err(Errno::EBADFD) | Err(Errno::EIO) => return Ok(()),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+
+            self.iface.poll(now(), &mut self.device, &mut self.sockets);
+            self.pump_tcp();
+            self.pump_udp()?;
+            self.iface.poll(now(), &mut self.device, &mut self.sockets);
+
+            self.wait()?;
+        }
+
+        Ok(())
+    }
+
+    fn wait(&mut self) -> Result<()> {
+        let mut fds = vec![PollFd::new(self.device.fd, PollFlags::POLLIN)];
+        fds.extend(
+            self.tcp_connections
+                .values()
+                .filter_map(|connection| connection.stream.as_ref())
+                .map(|stream| PollFd::new(stream.as_raw_fd(), PollFlags::POLLIN)),
+        );
+        fds.extend(
+            self.udp_flows
+                .values()
+                .map(|flow| PollFd::new(flow.socket.as_raw_fd(), PollFlags::POLLIN)),
+        );
+
+        let timeout = match self.iface.poll_delay(now(), &self.sockets) {
+            Some(delay) => (delay.total_millis() as i32).min(POLL_TIMEOUT_MS),
+            None => POLL_TIMEOUT_MS,
+        };
+
+        match poll(&mut fds, timeout) {
+            Ok(_) | Err(Errno::EINTR) => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    fn handle_frame(&mut self, frame: &[u8]) {
+        let ethernet = match EthernetFrame::new_checked(frame) {
+            Ok(ethernet) => ethernet,
+            Err(_) => return,
+        };
+        self.container_mac = Some(ethernet.src_addr());
+
+        if ethernet.ethertype() == EthernetProtocol::Ipv4 {
+            if let Ok(ip) = Ipv4Packet::new_checked(ethernet.payload()) {
+                match ip.next_header() {
+                    IpProtocol::Udp => {
+                        if let Ok(udp) = UdpPacket::new_checked(ip.payload()) {
+                            self.handle_udp(ip.src_addr(), ip.dst_addr(), &udp);
+                        }
+                        return;
+                    }
+                    IpProtocol::Tcp => {
+                        if let Ok(tcp) = TcpPacket::new_checked(ip.payload()) {
+                            if tcp.syn() && !tcp.ack() {
+                                self.handle_tcp_syn(ip.src_addr(), ip.dst_addr(), &tcp);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        self.device.rx_queue.push_back(frame.to_vec());
+    }
+
+    /// Returns `None` when container is not allowed to connect to `address`.
+    fn host_address(&self, address: Ipv4Address, port: u16) -> Option<SocketAddr> {
+        let address = Ipv4Addr::from(address);
+
+        let address = if address == GATEWAY_ADDRESS {
+            if !self.host_loopback {
+                return None;
+            }
+            Ipv4Addr::LOCALHOST
+        } else if address == DNS_ADDRESS && port == 53 {
+            self.dns_server
+        } else {
+            address
+        };
+
+        Some(SocketAddr::V4(SocketAddrV4::new(address, port)))
+    }
+
+    fn handle_tcp_syn(&mut self, src: Ipv4Address, dst: Ipv4Address, tcp: &TcpPacket<&[u8]>) {
+        let key = (src, tcp.src_port(), dst, tcp.dst_port());
+        if self.tcp_connections.contains_key(&key) {
+            return;
+        }
+
+        // connection without listening socket is reset
+        let host_address = match self.host_address(dst, tcp.dst_port()) {
+            Some(host_address) => host_address,
+            None => return,
+        };
+
+        let mut socket = tcp::Socket::new(
+            tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+            tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+        );
+        let endpoint = IpListenEndpoint {
+            addr: Some(IpAddress::Ipv4(dst)),
+            port: tcp.dst_port(),
+        };
+        if socket.listen(endpoint).is_err() {
+            return;
+        }
+
+        let (sender, receiver) = channel();
+        thread::spawn(move || {
+            let _ = sender.send(TcpStream::connect_timeout(
+                &host_address,
+                Duration::from_secs(10),
+            ));
+        });
+
+        self.tcp_connections.insert(
+            key,
+            TcpConnection {
+                handle: self.sockets.add(socket),
+                connecting: Some(receiver),
+                stream: None,
+                pending: vec![],
+                established: false,
+                host_closed: false,
+                container_closed: false,
+            },
+        );
+    }
+
+    fn pump_tcp(&mut self) {
+        let mut closed = vec![];
+
+        for (key, connection) in self.tcp_connections.iter_mut() {
+            let socket = self.sockets.get_mut::<tcp::Socket>(connection.handle);
+
+            if let Some(receiver) = &connection.connecting {
+                match receiver.try_recv() {
+                    Ok(Ok(stream)) if stream.set_nonblocking(true).is_ok() => {
+                        connection.stream = Some(stream);
+                        connection.connecting = None;
+                    }
+                    Err(TryRecvError::Empty) => {}
+                    _ => {
+                        socket.abort();
+                        connection.connecting = None;
+                    }
+                }
+            }
+
+            if socket.state() == tcp::State::Established {
+                connection.established = true;
+            }
+
+            if let Some(mut stream) = connection.stream.take() {
+                if !pump_tcp_connection(socket, &mut stream, connection) {
+                    socket.abort();
+                }
+                connection.stream = Some(stream);
+            }
+
+            if connection.connecting.is_none()
+                && matches!(socket.state(), tcp::State::Closed | tcp::State::TimeWait)
+            {
+                closed.push(*key);
+            }
+        }
+
+        for key in closed {
+            if let Some(connection) = self.tcp_connections.remove(&key) {
+                self.sockets.remove(connection.handle);
+            }
+        }
+    }
+
+    fn handle_udp(&mut self, src: Ipv4Address, dst: Ipv4Address, udp: &UdpPacket<&[u8]>) {
+        let key = (src, udp.src_port(), dst, udp.dst_port());
+
+        if !self.udp_flows.contains_key(&key) {
+            let host_address = match self.host_address(dst, udp.dst_port()) {
+                Some(host_address) => host_address,
+                None => return,
+            };
+
+            let socket = match UdpSocket::bind("0.0.0.0:0")
+                .and_then(|socket| socket.connect(host_address).map(|_| socket))
+                .and_then(|socket| socket.set_nonblocking(true).map(|_| socket))
+            {
+                Ok(socket) => socket,
+                Err(_) => return,
+            };
+
+            self.udp_flows.insert(
+                key,
+                UdpFlow {
+                    socket,
+                    container_port: udp.src_port(),
+                    remote_address: dst,
+                    remote_port: udp.dst_port(),
+                    last_used: std::time::Instant::now(),
+                },
+            );
+        }
+
+        if let Some(flow) = self.udp_flows.get_mut(&key) {
+            let _ = flow.socket.send(udp.payload());
+            flow.last_used = std::time::Instant::now();
+        }
+    }
+
+    fn pump_udp(&mut self) -> Result<()> {
+        let mut buf = vec![0u8; MTU];
+        let container_mac = match self.container_mac {
+            Some(container_mac) => container_mac,
+            None => return Ok(()),
+        };
+
+        for flow in self.udp_flows.values_mut() {
+            loop {
+                match flow.socket.recv(&mut buf) {
+                    Ok(len) => {
+                        let frame = udp_frame(container_mac, flow, &buf[..len]);
+                        let _ = unistd::write(self.device.fd, &frame);
+                        flow.last_used = std::time::Instant::now();
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(_) => break,
+                }
+            }
+        }
+
+        self.udp_flows
+            .retain(|_, flow| flow.last_used.elapsed() < UDP_FLOW_TIMEOUT);
+
+        Ok(())
+    }
+}
+
+/// Moves data between container's socket and host stream. Returns `false` if
+/// connection should be aborted.
+fn pump_tcp_connection(
+    socket: &mut tcp::Socket,
+    stream: &mut TcpStream,
+    connection: &mut TcpConnection,
+) -> bool {
+    // container -> host
+    if connection.pending.is_empty() && socket.can_recv() {
+        let pending = &mut connection.pending;
+        let _ = socket.recv(|data| {
+            pending.extend_from_slice(data);
+            (data.len(), ())
+        });
+    }
+
+    if !connection.pending.is_empty() {
+        match stream.write(&connection.pending) {
+            Ok(len) => {
+                connection.pending.drain(..len);
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => return false,
+        }
+    }
+
+    if connection.established
+        && !connection.container_closed
+        && connection.pending.is_empty()
+        && !socket.may_recv()
+    {
+        let _ = stream.shutdown(Shutdown::Write);
+        connection.container_closed = true;
+    }
+
+    // host -> container
+    if !connection.host_closed && socket.can_send() {
+        let mut buf = [0u8; 16 * 1024];
+        let space = (socket.send_capacity() - socket.send_queue()).min(buf.len());
+
+        match stream.read(&mut buf[..space]) {
+            Ok(0) => {
+                socket.close();
+                connection.host_closed = true;
+            }
+            Ok(len) => {
+                let _ = socket.send_slice(&buf[..len]);
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {}
+            Err(_) => return false,
+        }
+    }
+
+    true
+}
+
+fn udp_frame(container_mac: EthernetAddress, flow: &UdpFlow, payload: &[u8]) -> Vec<u8> {
+    let src_address = IpAddress::Ipv4(flow.remote_address);
+    let dst_address = IpAddress::Ipv4(CONTAINER_ADDRESS.into());
+
+    let ethernet_repr = EthernetRepr {
+        src_addr: MAC_ADDRESS,
+        dst_addr: container_mac,
+        ethertype: EthernetProtocol::Ipv4,
+    };
+    let udp_repr = UdpRepr {
+        src_port: flow.remote_port,
+        dst_port: flow.container_port,
+    };
+    let ip_repr = Ipv4Repr {
+        src_addr: flow.remote_address,
+        dst_addr: CONTAINER_ADDRESS.into(),
+        next_header: IpProtocol::Udp,
+        payload_len: udp_repr.header_len() + payload.len(),
+        hop_limit: 64,
+    };
+
+    let mut buf =
+        vec![0u8; ethernet_repr.buffer_len() + ip_repr.buffer_len() + ip_repr.payload_len];
+    let checksum_caps = ChecksumCapabilities::default();
+
+    let mut frame = EthernetFrame::new_unchecked(&mut buf);
+    ethernet_repr.emit(&mut frame);
+
+    let mut ip = Ipv4Packet::new_unchecked(frame.payload_mut());
+    ip_repr.emit(&mut ip, &checksum_caps);
+
+    let mut udp = UdpPacket::new_unchecked(ip.payload_mut());
+    udp_repr.emit(
+        &mut udp,
+        &src_address,
+        &dst_address,
+        payload.len(),
+        |buf| buf.copy_from_slice(payload),
+        &checksum_caps,
+    );
+
+    buf
+}
+
+fn now() -> Instant {
+    Instant::now()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use nix::sys::socket::{socketpair, AddressFamily, SockFlag, SockType};
+
+    use super::*;
+
+    const CONTAINER_MAC: EthernetAddress = EthernetAddress([0x52, 0x55, 0x0a, 0x00, 0x02, 0x64]);
+
+    /// Sends HTTP request from container's side of a network stack to the
+    /// gateway at `port` and returns the response.
+    fn http_request_through_gateway(port: u16, host_loopback: bool) -> Vec<u8> {
+        // packet socket pair keeps frame boundaries, same as tap device
+        let (host_fd, container_fd) = socketpair(
+            AddressFamily::Unix,
+            SockType::SeqPacket,
+            None,
+            SockFlag::empty(),
+        )
+        .unwrap();
+        fcntl(container_fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).unwrap();
+        let stack = Stack::spawn(host_fd, host_loopback).unwrap();
+
+        // container's side of the network
+        let mut device = TapDevice {
+            fd: container_fd,
+            rx_queue: VecDeque::new(),
+        };
+        let mut iface = Interface::new(Config::new(CONTAINER_MAC.into()), &mut device, now());
+        iface.update_ip_addrs(|addrs| {
+            addrs
+                .push(IpCidr::new(
+                    Ipv4Address::from(CONTAINER_ADDRESS).into(),
+                    PREFIX_LEN,
+                ))
+                .unwrap();
+        });
+        iface
+            .routes_mut()
+            .add_default_ipv4_route(GATEWAY_ADDRESS.into())
+            .unwrap();
+
+        let mut sockets = SocketSet::new(vec![]);
+        let handle = sockets.add(tcp::Socket::new(
+            tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+            tcp::SocketBuffer::new(vec![0; TCP_BUFFER_SIZE]),
+        ));
+        sockets
+            .get_mut::<tcp::Socket>(handle)
+            .connect(
+                iface.context(),
+                (IpAddress::Ipv4(GATEWAY_ADDRESS.into()), port),
+                49152,
+            )
+            .unwrap();
+
+        let mut response = vec![];
+        let mut sent = false;
+        let mut buf = vec![0u8; 65536];
+        let deadline = std::time::Instant::now() + Duration::from_secs(10);
+
+        while std::time::Instant::now() < deadline && !response.ends_with(b"hello") {
+            while let Ok(len) = unistd::read(container_fd, &mut buf) {
+                if len == 0 {
+                    break;
+                }
+                device.rx_queue.push_back(buf[..len].to_vec());
+            }
+            iface.poll(now(), &mut device, &mut sockets);
+
+            let socket = sockets.get_mut::<tcp::Socket>(handle);
+            // refused connection is reset
+            if !socket.is_open() {
+                break;
+            }
+            if socket.can_send() && !sent {
+                socket.send_slice(b"GET / HTTP/1.0\r\n\r\n").unwrap();
+                sent = true;
+            }
+            if socket.can_recv() {
+                socket
+                    .recv(|data| {
+                        response.extend_from_slice(data);
+                        (data.len(), ())
+                    })
+                    .unwrap();
+            }
+            iface.poll(now(), &mut device, &mut sockets);
+
+            thread::sleep(Duration::from_millis(5));
+        }
+
+        stack.stop().unwrap();
+        unistd::close(container_fd).unwrap();
+
+        response
+    }
+
+    #[test]
+    fn http_request_to_host_through_gateway() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0u8; 1024];
+            let len = stream.read(&mut request).unwrap();
+            assert!(request[..len].starts_with(b"GET / HTTP/1.0"));
+
+            stream
+                .write_all(b"HTTP/1.0 200 OK\r\nContent-Length: 5\r\n\r\nhello")
+                .unwrap();
+        });
+
+        let response = http_request_through_gateway(port, true);
+        server.join().unwrap();
+
+        assert!(response.starts_with(b"HTTP/1.0 200 OK"));
+        assert!(response.ends_with(b"hello"));
+    }
+
+    #[test]
+    fn gateway_refuses_connections_to_host_loopback_by_default() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let response = http_request_through_gateway(port, false);
+
+        assert!(response.is_empty());
+        listener.set_nonblocking(true).unwrap();
+        assert_eq!(listener.accept().unwrap_err().kind(), ErrorKind::WouldBlock);
+    }
+}
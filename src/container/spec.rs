@@ -0,0 +1,66 @@
+use std::{
+    fs::{read_to_string, write},
+    net::IpAddr,
+    path::PathBuf,
+};
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    container::{
+        cgroups, devices::Device, env::EnvVariable, idmap::IdMap, ipam::Subnet,
+        network::NetworkMode, ports::PortMapping, security, state::State,
+    },
+    image::ImageId,
+    volume::Volume,
+};
+
+/// Configuration the container is started with, kept in container's directory
+/// so that a stopped container can be started again.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Spec {
+    pub image: ImageId,
+    /// Directory of the pulled image
+    pub image_path: PathBuf,
+    pub command: Vec<String>,
+    pub env: Vec<EnvVariable>,
+    /// User spec resolved inside of the container (name|uid[:group|gid])
+    pub user: String,
+    pub group_add: Vec<String>,
+    pub workdir: PathBuf,
+    pub volumes: Vec<Volume>,
+    pub devices: Vec<Device>,
+    pub shm_size: u64,
+    pub cgroups: cgroups::Config,
+    pub security: security::Config,
+    pub uid_mappings: Vec<IdMap>,
+    pub gid_mappings: Vec<IdMap>,
+    pub network: NetworkMode,
+    pub subnet: Option<Subnet>,
+    /// Requested port mappings, free host ports are picked on every start
+    pub publish: Vec<PortMapping>,
+    pub dns: Vec<IpAddr>,
+    pub dns_search: Vec<String>,
+    pub read_only: bool,
+    /// Remove the container when it exits
+    pub auto_remove: bool,
+}
+
+impl Spec {
+    fn path(state: &State) -> Result<PathBuf> {
+        Ok(state.dir()?.join("config.json"))
+    }
+
+    pub fn load(state: &State) -> Result<Self> {
+        let content = read_to_string(Self::path(state)?)?;
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self, state: &State) -> Result<()> {
+        write(Self::path(state)?, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+}
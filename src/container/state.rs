@@ -0,0 +1,177 @@
+use std::{
+    fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, write},
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, bail, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    container::{bundle::Bundle, hosts::HostEntry, ports::PortMapping, storage::StorageDriver},
+    util::{data_root, generate_id},
+    volume::NamedVolume,
+};
+
+pub fn containers_dir() -> Result<PathBuf> {
+    Ok(data_root()?.join("containers"))
+}
+
+/// Container metadata persisted in container's directory.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct State {
+    pub id: String,
+    pub name: String,
+    pub image: String,
+    #[serde(default)]
+    pub hostname: String,
+    #[serde(default)]
+    pub domainname: String,
+    #[serde(default)]
+    pub extra_hosts: Vec<HostEntry>,
+    pub pid: Option<i32>,
+    #[serde(default)]
+    pub ports: Vec<PortMapping>,
+    /// Names of con-managed volumes used by the container
+    #[serde(default)]
+    pub volumes: Vec<String>,
+    #[serde(default)]
+    pub storage_driver: Option<StorageDriver>,
+    /// Size limit of the writable layer
+    #[serde(default)]
+    pub storage_size: Option<u64>,
+}
+
+impl State {
+    pub fn new(name: Option<String>, image: String, hostname: String) -> Result<Self> {
+        let id = generate_id()?;
+        let name = name.unwrap_or_else(|| id[..12].to_string());
+
+        if Self::list()?.iter().any(|state| state.name == name) {
+            bail!("Container name {} is already in use", name);
+        }
+
+        Ok(Self {
+            id,
+            name,
+            image,
+            hostname,
+            domainname: String::new(),
+            extra_hosts: vec![],
+            pid: None,
+            ports: vec![],
+            volumes: vec![],
+            storage_driver: None,
+            storage_size: None,
+        })
+    }
+
+    pub fn dir(&self) -> Result<PathBuf> {
+        Ok(containers_dir()?.join(&self.id))
+    }
+
+    fn load(dir: &Path) -> Result<Self> {
+        let content = read_to_string(dir.join("state.json"))?;
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let dir = self.dir()?;
+        create_dir_all(&dir)?;
+        write(dir.join("state.json"), serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+
+    pub fn list() -> Result<Vec<Self>> {
+        let entries = match read_dir(containers_dir()?) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut states = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.join("state.json").exists() {
+                states.push(Self::load(&path)?);
+            }
+        }
+
+        Ok(states)
+    }
+
+    /// Finds container by name, full id or unique id prefix.
+    pub fn find(name_or_id: &str) -> Result<Self> {
+        let states = Self::list()?;
+
+        if let Some(state) = states.iter().find(|state| state.name == name_or_id) {
+            return Ok(state.clone());
+        }
+
+        let mut matching = states
+            .into_iter()
+            .filter(|state| state.id.starts_with(name_or_id));
+
+        match (matching.next(), matching.next()) {
+            (Some(state), None) => Ok(state),
+            (Some(_), Some(_)) => Err(anyhow!("Container id {} is ambiguous", name_or_id)),
+            (None, _) => Err(anyhow!("No such container: {}", name_or_id)),
+        }
+    }
+
+    /// Removes container's directory, together with its anonymous volumes if
+    /// `volumes` is set.
+    pub fn remove(&self, volumes: bool) -> Result<()> {
+        if self.is_running() {
+            bail!("Container {} is running", self.name);
+        }
+
+        remove_dir_all(self.dir()?)?;
+
+        if volumes {
+            self.remove_anonymous_volumes()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn remove_anonymous_volumes(&self) -> Result<()> {
+        for name in &self.volumes {
+            if let Ok(volume) = NamedVolume::load(name) {
+                if volume.anonymous {
+                    volume.remove()?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Overlay upperdir with container's changes as seen from the host, `None`
+    /// if the storage driver does not use one.
+    pub fn upperdir(&self) -> Result<Option<PathBuf>> {
+        match self.storage_driver {
+            Some(StorageDriver::Overlay | StorageDriver::FuseOverlayfs) => {}
+            _ => return Ok(None),
+        }
+
+        let upperdir = Bundle::upperdir_path_inner(&self.dir()?);
+
+        // size limited layer is mounted only in container's mount namespace
+        match self.pid {
+            Some(pid) if self.is_running() => Ok(Some(
+                PathBuf::from(format!("/proc/{}/root", pid)).join(upperdir.strip_prefix("/")?),
+            )),
+            _ => Ok(Some(upperdir)),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        match self.pid {
+            Some(pid) => Path::new(&format!("/proc/{}", pid)).exists(),
+            None => false,
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    env,
+    fmt::{self, Display},
+    fs::{
+        copy, create_dir, read_dir, read_link, read_to_string, remove_dir_all, remove_file,
+        symlink_metadata, File,
+    },
+    os::unix::fs::symlink,
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+};
+
+use anyhow::{bail, Result};
+use nix::{
+    mount::{mount, umount2, MntFlags, MsFlags},
+    sys::utsname::uname,
+    unistd::getuid,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::util::{copy_metadata, parse_size};
+
+/// Prefix of OCI whiteout file hiding the file without the prefix
+pub const WHITEOUT_PREFIX: &str = ".wh.";
+/// OCI whiteout file hiding content of lower layers in its directory
+pub const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+/// Way of assembling container's root file system from image layers.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum StorageDriver {
+    /// Kernel overlayfs
+    Overlay,
+    /// overlayfs implemented in userspace by `fuse-overlayfs` program
+    FuseOverlayfs,
+    /// Layers copied into a plain directory
+    Vfs,
+}
+
+impl FromStr for StorageDriver {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "overlay" => Ok(Self::Overlay),
+            "fuse-overlayfs" => Ok(Self::FuseOverlayfs),
+            "vfs" => Ok(Self::Vfs),
+            _ => Err("Invalid storage driver. Expected one of 'overlay', 'fuse-overlayfs', 'vfs'"),
+        }
+    }
+}
+
+impl Display for StorageDriver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Overlay => write!(f, "overlay"),
+            Self::FuseOverlayfs => write!(f, "fuse-overlayfs"),
+            Self::Vfs => write!(f, "vfs"),
+        }
+    }
+}
+
+/// Storage option of a container given as `key=value`.
+#[derive(Clone, Debug)]
+pub enum StorageOpt {
+    /// Maximum size of container's writable layer
+    Size(u64),
+}
+
+impl FromStr for StorageOpt {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once("=") {
+            Some(("size", size)) => Ok(Self::Size(parse_size(size)?)),
+            _ => Err("Invalid storage option. Expected 'size=<size>'"),
+        }
+    }
+}
+
+impl StorageDriver {
+    /// Picks kernel overlayfs if it can be mounted by the current user, then
+    /// `fuse-overlayfs` if it is installed and `vfs` otherwise.
+    pub fn detect() -> Result<Self> {
+        let overlay_supported = read_to_string("/proc/filesystems")?
+            .lines()
+            .any(|line| line.split_whitespace().last() == Some("overlay"));
+
+        // unprivileged overlay mounts are supported since Linux 5.11
+        if overlay_supported && (getuid().is_root() || kernel_version() >= (5, 11)) {
+            return Ok(Self::Overlay);
+        }
+
+        if Path::new("/dev/fuse").exists() && find_program("fuse-overlayfs").is_some() {
+            return Ok(Self::FuseOverlayfs);
+        }
+
+        Ok(Self::Vfs)
+    }
+
+    /// Mounts root file system made of `layers` (ordered from the base one)
+    /// at `root`. Changes are written to `upperdir` using `workdir` by overlay
+    /// drivers.
+    pub fn mount(
+        &self,
+        layers: &[PathBuf],
+        root: &Path,
+        upperdir: &Path,
+        workdir: &Path,
+    ) -> Result<()> {
+        match self {
+            Self::Overlay => {
+                mount(
+                    None::<&str>,
+                    root,
+                    Some("overlay"),
+                    MsFlags::empty(),
+                    Some(overlay_options(layers, upperdir, workdir).as_str()),
+                )?;
+            }
+            Self::FuseOverlayfs => {
+                let status = Command::new("fuse-overlayfs")
+                    .arg("-o")
+                    .arg(overlay_options(layers, upperdir, workdir))
+                    .arg(root)
+                    .spawn()?
+                    .wait()?;
+
+                if !status.success() {
+                    bail!("fuse-overlayfs failed with {}", status);
+                }
+            }
+            Self::Vfs => {
+                // pivot_root requires new root to be a mount point
+                mount(
+                    Some(root),
+                    root,
+                    None::<&str>,
+                    MsFlags::MS_BIND,
+                    None::<&str>,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Copies `layers` (ordered from the base one) to `root` of vfs driver
+    /// once, which is recorded by `marker` file. Other drivers use the layers
+    /// as they are.
+    pub fn populate(&self, layers: &[PathBuf], root: &Path, marker: &Path) -> Result<()> {
+        if *self != Self::Vfs || marker.exists() {
+            return Ok(());
+        }
+
+        for layer in layers {
+            apply_layer(layer, root)?;
+        }
+        File::create(marker)?;
+
+        Ok(())
+    }
+
+    pub fn unmount(&self, root: &Path) -> Result<()> {
+        umount2(root, MntFlags::MNT_DETACH)?;
+
+        Ok(())
+    }
+}
+
+fn overlay_options(layers: &[PathBuf], upperdir: &Path, workdir: &Path) -> String {
+    // lowerdir lists the topmost layer first
+    let lowerdirs: Vec<String> = layers
+        .iter()
+        .rev()
+        .map(|layer| layer.to_str().unwrap().to_string())
+        .collect();
+
+    format!(
+        "lowerdir={},upperdir={},workdir={}",
+        lowerdirs.join(":"),
+        upperdir.to_str().unwrap(),
+        workdir.to_str().unwrap()
+    )
+}
+
+/// Copies content of unpacked `layer` over `target`, removing files hidden by
+/// whiteouts of the layer.
+fn apply_layer(layer: &Path, target: &Path) -> Result<()> {
+    if layer.join(OPAQUE_WHITEOUT).exists() {
+        for entry in read_dir(target)? {
+            remove_path(&entry?.path())?;
+        }
+    }
+
+    for entry in read_dir(layer)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+
+        if name == OPAQUE_WHITEOUT {
+            continue;
+        }
+
+        if let Some(hidden) = name.strip_prefix(WHITEOUT_PREFIX) {
+            let hidden = target.join(hidden);
+            if symlink_metadata(&hidden).is_ok() {
+                remove_path(&hidden)?;
+            }
+            continue;
+        }
+
+        let destination = target.join(entry.file_name());
+        let metadata = entry.path().symlink_metadata()?;
+        let file_type = metadata.file_type();
+
+        match symlink_metadata(&destination) {
+            Ok(existing) if existing.is_dir() && file_type.is_dir() => {}
+            Ok(_) => remove_path(&destination)?,
+            Err(_) => {}
+        }
+
+        if file_type.is_dir() {
+            if !destination.exists() {
+                create_dir(&destination)?;
+            }
+            apply_layer(&entry.path(), &destination)?;
+            copy_metadata(&metadata, &destination)?;
+        } else if file_type.is_symlink() {
+            symlink(read_link(entry.path())?, &destination)?;
+            copy_metadata(&metadata, &destination)?;
+        } else if file_type.is_file() {
+            copy(entry.path(), &destination)?;
+            copy_metadata(&metadata, &destination)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn remove_path(path: &Path) -> Result<()> {
+    if symlink_metadata(path)?.is_dir() {
+        remove_dir_all(path)?;
+    } else {
+        remove_file(path)?;
+    }
+
+    Ok(())
+}
+
+fn kernel_version() -> (u32, u32) {
+    let uts = uname();
+    let mut numbers = uts
+        .release()
+        .split(|c: char| !c.is_ascii_digit())
+        .map(|number| number.parse().unwrap_or(0));
+
+    (numbers.next().unwrap_or(0), numbers.next().unwrap_or(0))
+}
+
+fn find_program(name: &str) -> Option<PathBuf> {
+    env::var_os("PATH").and_then(|paths| {
+        env::split_paths(&paths)
+            .map(|path| path.join(name))
+            .find(|path| path.is_file())
+    })
+}
@@ -0,0 +1,161 @@
+use std::{
+    fs::read_to_string,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Result};
+use nix::unistd::{setgid, setgroups, setuid, Gid, Uid};
+
+struct PasswdEntry {
+    name: String,
+    uid: u32,
+    gid: u32,
+    home: PathBuf,
+}
+
+struct GroupEntry {
+    name: String,
+    gid: u32,
+    members: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct User {
+    pub uid: u32,
+    pub gid: u32,
+    pub additional_gids: Vec<u32>,
+    pub home: PathBuf,
+}
+
+fn read_lines(path: &Path) -> Result<Vec<Vec<String>>> {
+    let content = match read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(err.into()),
+    };
+
+    Ok(content
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with("#"))
+        .map(|line| line.split(":").map(|part| part.to_string()).collect())
+        .collect())
+}
+
+fn passwd_entries(root: &Path) -> Result<Vec<PasswdEntry>> {
+    Ok(read_lines(&root.join("etc/passwd"))?
+        .into_iter()
+        .filter_map(|parts| match parts.as_slice() {
+            [name, _, uid, gid, _, home, ..] => match (uid.parse(), gid.parse()) {
+                (Ok(uid), Ok(gid)) => Some(PasswdEntry {
+                    name: name.clone(),
+                    uid,
+                    gid,
+                    home: PathBuf::from(home),
+                }),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect())
+}
+
+fn group_entries(root: &Path) -> Result<Vec<GroupEntry>> {
+    Ok(read_lines(&root.join("etc/group"))?
+        .into_iter()
+        .filter_map(|parts| match parts.as_slice() {
+            [name, _, gid, members, ..] => match gid.parse() {
+                Ok(gid) => Some(GroupEntry {
+                    name: name.clone(),
+                    gid,
+                    members: members
+                        .split(",")
+                        .filter(|member| !member.is_empty())
+                        .map(|member| member.to_string())
+                        .collect(),
+                }),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect())
+}
+
+fn resolve_group(groups: &[GroupEntry], group: &str) -> Result<u32> {
+    if let Ok(gid) = group.parse() {
+        return Ok(gid);
+    }
+
+    groups
+        .iter()
+        .find(|entry| entry.name == group)
+        .map(|entry| entry.gid)
+        .ok_or_else(|| anyhow!("Group {} not found in container", group))
+}
+
+/// Resolves `spec` in format `name|uid[:group|gid]` against `/etc/passwd` and
+/// `/etc/group` of the file system at `root`.
+pub fn resolve(root: &Path, spec: &str, group_add: &[String]) -> Result<User> {
+    let (user, group) = match spec.split_once(":") {
+        Some((user, group)) => (user, Some(group)),
+        None => (spec, None),
+    };
+
+    let passwd = passwd_entries(root)?;
+    let groups = group_entries(root)?;
+
+    let entry = match user.parse::<u32>() {
+        Ok(uid) => passwd.iter().find(|entry| entry.uid == uid),
+        Err(_) => passwd.iter().find(|entry| entry.name == user),
+    };
+
+    let (uid, gid, home, name) = match (entry, user.parse::<u32>()) {
+        (Some(entry), _) => (entry.uid, entry.gid, entry.home.clone(), Some(&entry.name)),
+        (None, Ok(uid)) => (uid, 0, PathBuf::from("/"), None),
+        (None, Err(_)) => return Err(anyhow!("User {} not found in container", user)),
+    };
+
+    let gid = match group {
+        Some(group) => resolve_group(&groups, group)?,
+        None => gid,
+    };
+
+    let mut additional_gids = vec![gid];
+
+    if let Some(name) = name {
+        additional_gids.extend(
+            groups
+                .iter()
+                .filter(|entry| entry.members.contains(name))
+                .map(|entry| entry.gid),
+        );
+    }
+
+    for group in group_add {
+        additional_gids.push(resolve_group(&groups, group)?);
+    }
+
+    additional_gids.sort_unstable();
+    additional_gids.dedup();
+
+    Ok(User {
+        uid,
+        gid,
+        additional_gids,
+        home,
+    })
+}
+
+pub fn switch(user: &User) -> Result<()> {
+    let additional_gids: Vec<Gid> = user
+        .additional_gids
+        .iter()
+        .map(|gid| Gid::from_raw(*gid))
+        .collect();
+
+    setgroups(&additional_gids)?;
+    setgid(Gid::from_raw(user.gid))?;
+    setuid(Uid::from_raw(user.uid))?;
+
+    Ok(())
+}
@@ -1,10 +1,18 @@
-use anyhow::Result;
-use oci_spec::image::{ImageConfiguration, ImageIndex, ImageManifest};
-use std::path::PathBuf;
+use anyhow::{bail, Result};
+use oci_spec::image::{
+    Config, Descriptor, ImageConfiguration, ImageIndex, ImageManifest, ANNOTATION_REF_NAME,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::{create_dir_all, write},
+    path::PathBuf,
+    str::FromStr,
+};
 
 use crate::util::blob_path;
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct ImageId {
     pub name: String,
     pub tag: String,
@@ -53,7 +61,26 @@ impl Image {
     pub fn new(name: String, tag: String, base_path: PathBuf) -> Result<Self> {
         let index = ImageIndex::from_file(base_path.join("index.json"))?;
 
-        let manifest_digest = &index.manifests()[0].digest();
+        // images with more tags have manifests annotated with the tag name,
+        // pulled ones have a single manifest without it
+        let ref_name = |manifest: &Descriptor| {
+            manifest
+                .annotations()
+                .as_ref()
+                .and_then(|annotations| annotations.get(ANNOTATION_REF_NAME))
+                .cloned()
+        };
+        let manifest_digest = match index
+            .manifests()
+            .iter()
+            .find(|manifest| ref_name(manifest).as_ref() == Some(&tag))
+        {
+            Some(manifest) => manifest.digest(),
+            None => match index.manifests().as_slice() {
+                [manifest] if ref_name(manifest).is_none() => manifest.digest(),
+                _ => bail!("No such tag {} of image {}", tag, name),
+            },
+        };
         let manifest = ImageManifest::from_file(blob_path(&base_path, &manifest_digest))?;
 
         let configuration_digest = &manifest.config().digest();
@@ -78,3 +105,134 @@ impl Image {
             .collect()
     }
 }
+
+/// Stores `content` in blob directory of the image at `base_path` and returns
+/// its digest.
+pub fn write_blob(base_path: &PathBuf, content: &[u8]) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+    let digest = format!("sha256:{:x}", hasher.finalize());
+
+    let path = blob_path(base_path, &digest);
+    create_dir_all(path.parent().unwrap())?;
+    write(path, content)?;
+
+    Ok(digest)
+}
+
+/// Change of image configuration given as Dockerfile instruction, e.g.
+/// `CMD ["sh"]`.
+#[derive(Clone, Debug)]
+pub enum ConfigChange {
+    Cmd(Vec<String>),
+    Entrypoint(Vec<String>),
+    Env(String, String),
+    Expose(String),
+    Label(String, String),
+    User(String),
+    Volume(Vec<String>),
+    Workdir(String),
+}
+
+impl FromStr for ConfigChange {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (instruction, value) = match s.trim().split_once(char::is_whitespace) {
+            Some((instruction, value)) if !value.trim().is_empty() => (instruction, value.trim()),
+            _ => return Err("Invalid change syntax. Expected in format 'INSTRUCTION value'"),
+        };
+
+        match instruction.to_uppercase().as_str() {
+            "CMD" => Ok(Self::Cmd(parse_command(value))),
+            "ENTRYPOINT" => Ok(Self::Entrypoint(parse_command(value))),
+            "ENV" => parse_pair(value).map(|(key, value)| Self::Env(key, value)),
+            "EXPOSE" => {
+                let (port, protocol) = value.split_once("/").unwrap_or((value, "tcp"));
+                match (port.parse::<u16>(), protocol) {
+                    (Ok(port), "tcp" | "udp") => Ok(Self::Expose(format!("{}/{}", port, protocol))),
+                    _ => Err("Invalid exposed port. Expected in format 'port[/tcp|udp]'"),
+                }
+            }
+            "LABEL" => parse_pair(value).map(|(key, value)| Self::Label(key, value)),
+            "USER" => Ok(Self::User(value.to_string())),
+            "VOLUME" => Ok(Self::Volume(
+                serde_json::from_str(value)
+                    .unwrap_or_else(|_| value.split_whitespace().map(String::from).collect()),
+            )),
+            "WORKDIR" => Ok(Self::Workdir(value.to_string())),
+            _ => Err("Unsupported instruction. Expected one of CMD, ENTRYPOINT, ENV, EXPOSE, LABEL, USER, VOLUME, WORKDIR"),
+        }
+    }
+}
+
+impl ConfigChange {
+    pub fn apply(&self, config: &mut Config) {
+        match self {
+            Self::Cmd(cmd) => {
+                config.set_cmd(Some(cmd.clone()));
+            }
+            Self::Entrypoint(entrypoint) => {
+                config.set_entrypoint(Some(entrypoint.clone()));
+            }
+            Self::Env(key, value) => {
+                let mut env = config.env().clone().unwrap_or_default();
+                env.retain(|variable| variable.split_once("=").map(|(k, _)| k) != Some(key));
+                env.push(format!("{}={}", key, value));
+                config.set_env(Some(env));
+            }
+            Self::Expose(port) => {
+                let mut exposed_ports = config.exposed_ports().clone().unwrap_or_default();
+                if !exposed_ports.contains(port) {
+                    exposed_ports.push(port.clone());
+                }
+                config.set_exposed_ports(Some(exposed_ports));
+            }
+            Self::Label(key, value) => {
+                let mut labels = config.labels().clone().unwrap_or_default();
+                labels.insert(key.clone(), value.clone());
+                config.set_labels(Some(labels));
+            }
+            Self::User(user) => {
+                config.set_user(Some(user.clone()));
+            }
+            Self::Volume(paths) => {
+                let mut volumes = config.volumes().clone().unwrap_or_default();
+                for path in paths {
+                    if !volumes.contains(path) {
+                        volumes.push(path.clone());
+                    }
+                }
+                config.set_volumes(Some(volumes));
+            }
+            Self::Workdir(workdir) => {
+                config.set_working_dir(Some(workdir.clone()));
+            }
+        }
+    }
+}
+
+/// Command in exec form (JSON array) is used as is, shell form is run by
+/// `/bin/sh -c`.
+fn parse_command(value: &str) -> Vec<String> {
+    serde_json::from_str(value).unwrap_or_else(|_| {
+        vec![
+            String::from("/bin/sh"),
+            String::from("-c"),
+            value.to_string(),
+        ]
+    })
+}
+
+fn parse_pair(value: &str) -> Result<(String, String), &'static str> {
+    match value
+        .split_once("=")
+        .or_else(|| value.split_once(char::is_whitespace))
+    {
+        Some((key, value)) if !key.trim().is_empty() => Ok((
+            key.trim().to_string(),
+            value.trim().trim_matches('"').to_string(),
+        )),
+        _ => Err("Invalid key-value pair. Expected in format 'KEY=value'"),
+    }
+}
@@ -1,20 +1,42 @@
 use anyhow::Result;
 use clap::Parser;
-use con::commands::{pull, run};
+use con::commands::{
+    commit, cp, create, diff, inspect, network, port, pull, rm, run, start, volume,
+};
 use std::str;
 
 #[derive(Parser, Debug)]
 #[clap(author, about, version)]
 enum Opt {
+    Commit(commit::Commit),
+    Cp(cp::Cp),
+    Create(create::Create),
+    Diff(diff::Diff),
+    Inspect(inspect::Inspect),
+    Network(network::Network),
+    Port(port::Port),
     Pull(pull::Pull),
+    Rm(rm::Rm),
     Run(run::Run),
+    Start(start::Start),
+    Volume(volume::Volume),
 }
 
 fn main() -> Result<()> {
     let opt = Opt::parse();
 
     match opt {
+        Opt::Commit(commit) => commit.exec(),
+        Opt::Cp(cp) => cp.exec(),
+        Opt::Create(create) => create.exec(),
+        Opt::Diff(diff) => diff.exec(),
+        Opt::Inspect(inspect) => inspect.exec(),
+        Opt::Network(network) => network.exec(),
+        Opt::Port(port) => port.exec(),
         Opt::Pull(pull) => pull.exec(),
+        Opt::Rm(rm) => rm.exec(),
         Opt::Run(run) => run.exec(),
+        Opt::Start(start) => start.exec(),
+        Opt::Volume(volume) => volume.exec(),
     }
 }
@@ -1,4 +1,16 @@
-use std::path::PathBuf;
+use std::{
+    env,
+    fs::{
+        copy, create_dir, read_dir, read_link, set_permissions, symlink_metadata, File, Metadata,
+    },
+    io::Read,
+    os::unix::fs::{symlink, MetadataExt},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Result};
+use nix::unistd::{fchownat, getuid, FchownatFlags, Gid, Uid};
 
 pub fn split_digest<'a>(digest: &'a str) -> (&'a str, &'a str) {
     digest.split_once(":").unwrap()
@@ -8,3 +20,156 @@ pub fn blob_path(base_path: &PathBuf, digest: &str) -> PathBuf {
     let (alg, digest) = split_digest(digest);
     base_path.join(format!("blobs/{}/{}", alg, digest))
 }
+
+pub fn parse_size(s: &str) -> Result<u64, &'static str> {
+    let s = s.trim().to_lowercase();
+    let s = s.strip_suffix("b").unwrap_or(&s);
+
+    let (number, multiplier) = match s.chars().last() {
+        Some('k') => (&s[..s.len() - 1], 1 << 10),
+        Some('m') => (&s[..s.len() - 1], 1 << 20),
+        Some('g') => (&s[..s.len() - 1], 1 << 30),
+        _ => (s, 1),
+    };
+
+    match number.parse::<u64>() {
+        Ok(number) => number.checked_mul(multiplier).ok_or("Size is too large"),
+        Err(_) => Err("Invalid size. Expected number with optional suffix (k, m, g)"),
+    }
+}
+
+/// Directory where con keeps its state, `/var/lib/con` for root user and
+/// `$XDG_DATA_HOME/con` (or `~/.local/share/con`) otherwise.
+pub fn data_root() -> Result<PathBuf> {
+    if getuid().is_root() {
+        return Ok(PathBuf::from("/var/lib/con"));
+    }
+
+    match env::var_os("XDG_DATA_HOME") {
+        Some(data_home) if !data_home.is_empty() => Ok(PathBuf::from(data_home).join("con")),
+        _ => match env::var_os("HOME") {
+            Some(home) => Ok(PathBuf::from(home).join(".local/share/con")),
+            None => Err(anyhow!("Neither XDG_DATA_HOME nor HOME is set")),
+        },
+    }
+}
+
+pub fn generate_id() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    File::open("/dev/urandom")?.read_exact(&mut bytes)?;
+
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// Recursively copies content of `source` directory into existing
+/// `destination`, keeping ownership and permissions. Special files are
+/// skipped.
+pub fn copy_dir(source: &Path, destination: &Path) -> Result<()> {
+    copy_metadata(&symlink_metadata(source)?, destination)?;
+
+    for entry in read_dir(source)? {
+        let entry = entry?;
+        let target = destination.join(entry.file_name());
+        let metadata = entry.path().symlink_metadata()?;
+        let file_type = metadata.file_type();
+
+        if file_type.is_dir() {
+            create_dir(&target)?;
+            copy_dir(&entry.path(), &target)?;
+        } else if file_type.is_symlink() {
+            symlink(read_link(entry.path())?, &target)?;
+            copy_metadata(&metadata, &target)?;
+        } else if file_type.is_file() {
+            copy(entry.path(), &target)?;
+            copy_metadata(&metadata, &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Disk space used by files under `path`.
+pub fn dir_size(path: &Path) -> Result<u64> {
+    let mut size = 0;
+
+    for entry in read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.path().symlink_metadata()?;
+
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        }
+        size += metadata.blocks() * 512;
+    }
+
+    Ok(size)
+}
+
+/// Sets owner and permissions of `path` to the ones from `metadata`.
+pub fn copy_metadata(metadata: &Metadata, path: &Path) -> Result<()> {
+    fchownat(
+        None,
+        path,
+        Some(Uid::from_raw(metadata.uid())),
+        Some(Gid::from_raw(metadata.gid())),
+        FchownatFlags::NoFollowSymlink,
+    )?;
+
+    if !metadata.file_type().is_symlink() {
+        set_permissions(path, metadata.permissions())?;
+    }
+
+    Ok(())
+}
+
+/// Current time in RFC 3339 format (UTC).
+pub fn timestamp() -> String {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+
+    format_timestamp(seconds)
+}
+
+/// Formats seconds since the Unix epoch in RFC 3339 format (UTC).
+fn format_timestamp(seconds: u64) -> String {
+    let (days, time) = (seconds / 86400, seconds % 86400);
+
+    // civil date from days since 1970-01-01
+    let z = days as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        time / 3600,
+        time % 3600 / 60,
+        time % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_timestamp_of_known_dates() {
+        assert_eq!(format_timestamp(0), "1970-01-01T00:00:00Z");
+        // leap day
+        assert_eq!(format_timestamp(951782400), "2000-02-29T00:00:00Z");
+        // 2100 is not a leap year
+        assert_eq!(format_timestamp(4107542400), "2100-03-01T00:00:00Z");
+        assert_eq!(format_timestamp(1700000000), "2023-11-14T22:13:20Z");
+    }
+}
@@ -1,19 +1,172 @@
-use std::{path::PathBuf, str::FromStr};
+use std::{
+    collections::BTreeMap,
+    fs::{create_dir_all, read_dir, read_to_string, remove_dir_all, write},
+    io::ErrorKind,
+    path::PathBuf,
+    str::FromStr,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Result};
+use nix::mount::MsFlags;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-#[derive(Debug)]
+use crate::{
+    container::state::State,
+    util::{data_root, generate_id, parse_size},
+};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum VolumeKind {
+    /// Bind mount of host path
+    Bind,
+    /// Volume managed by con, anonymous one is created if name is not set
+    Named(Option<String>),
+    /// tmpfs with given mount data (e.g. size=64m,mode=1777)
+    Tmpfs(String),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Volume {
+    pub kind: VolumeKind,
+    /// Host path, data directory of named volume once it is resolved
     pub source: PathBuf,
     pub destination: PathBuf,
+    pub options: VolumeOptions,
+    /// Populate the volume with image's content at destination before it is
+    /// mounted
+    pub copy_up: bool,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct VolumeOptions {
+    pub readonly: bool,
+    /// Bind submounts of the source too
+    pub recursive: bool,
+    /// Mount propagation type (MS_SHARED, MS_SLAVE, MS_PRIVATE, optionally
+    /// with MS_REC)
+    #[serde(with = "optional_flags")]
+    pub propagation: Option<MsFlags>,
+    /// Additional flags applied on remount (MS_NOSUID, MS_NODEV, MS_NOEXEC)
+    #[serde(with = "flags")]
+    pub flags: MsFlags,
+    /// Do not copy image's content into a new named volume
+    pub nocopy: bool,
+    /// Map ids of bind mounted files through container's user namespace
+    pub idmap: bool,
+}
+
+impl Default for VolumeOptions {
+    fn default() -> Self {
+        Self {
+            readonly: false,
+            recursive: false,
+            propagation: None,
+            flags: MsFlags::empty(),
+            nocopy: false,
+            idmap: false,
+        }
+    }
+}
+
+/// Mount flags are persisted as their numeric value.
+mod flags {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(flags: &MsFlags, serializer: S) -> Result<S::Ok, S::Error> {
+        flags.bits().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<MsFlags, D::Error> {
+        Ok(MsFlags::from_bits_truncate(Deserialize::deserialize(
+            deserializer,
+        )?))
+    }
+}
+
+mod optional_flags {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        flags: &Option<MsFlags>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        flags.map(|flags| flags.bits()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<MsFlags>, D::Error> {
+        let bits: Option<_> = Deserialize::deserialize(deserializer)?;
+        Ok(bits.map(MsFlags::from_bits_truncate))
+    }
+}
+
+impl FromStr for VolumeOptions {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = Self::default();
+
+        for option in s.split(',') {
+            if !options.apply(option) {
+                return Err("Invalid volume option");
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+impl VolumeOptions {
+    /// Applies single option, returns `false` if it is not known.
+    fn apply(&mut self, option: &str) -> bool {
+        match option {
+            "ro" => self.readonly = true,
+            "rw" => self.readonly = false,
+            "rbind" => self.recursive = true,
+            "bind" => self.recursive = false,
+            "shared" => self.propagation = Some(MsFlags::MS_SHARED),
+            "rshared" => self.propagation = Some(MsFlags::MS_SHARED | MsFlags::MS_REC),
+            "slave" => self.propagation = Some(MsFlags::MS_SLAVE),
+            "rslave" => self.propagation = Some(MsFlags::MS_SLAVE | MsFlags::MS_REC),
+            "private" => self.propagation = Some(MsFlags::MS_PRIVATE),
+            "rprivate" => self.propagation = Some(MsFlags::MS_PRIVATE | MsFlags::MS_REC),
+            "nosuid" => self.flags |= MsFlags::MS_NOSUID,
+            "suid" => self.flags -= MsFlags::MS_NOSUID,
+            "nodev" => self.flags |= MsFlags::MS_NODEV,
+            "dev" => self.flags -= MsFlags::MS_NODEV,
+            "noexec" => self.flags |= MsFlags::MS_NOEXEC,
+            "exec" => self.flags -= MsFlags::MS_NOEXEC,
+            "nocopy" => self.nocopy = true,
+            "idmap" => self.idmap = true,
+            // SELinux relabeling is not supported
+            "z" | "Z" | "" => {}
+            _ => return false,
+        }
+
+        true
+    }
+
+    /// Bind mount has to be remounted to apply these options.
+    pub fn needs_remount(&self) -> bool {
+        self.readonly || !self.flags.is_empty()
+    }
 }
 
 impl FromStr for Volume {
     type Err = &'static str;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.split_once(":") {
-            Some((source, destination)) => {
+        let mut parts = s.splitn(3, ':');
+
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(source), Some(destination), options) => {
+                let kind = if is_volume_name(source) {
+                    VolumeKind::Named(Some(source.to_string()))
+                } else {
+                    VolumeKind::Bind
+                };
+
                 let source = match PathBuf::from_str(source) {
                     Ok(source) => source,
                     Err(_) => return Err("Source is not a path"),
@@ -24,12 +177,435 @@ impl FromStr for Volume {
                     Err(_) => return Err("Destination is not a path"),
                 };
 
+                let options = match options {
+                    Some(options) => VolumeOptions::from_str(options)?,
+                    None => VolumeOptions::default(),
+                };
+
                 Ok(Self {
+                    kind,
                     source,
                     destination,
+                    options,
+                    copy_up: false,
                 })
             }
-            None => Err("Invalid volume syntax. Expected in format 'source:destination'"),
+            _ => {
+                Err("Invalid volume syntax. Expected in format 'source|name:destination[:options]'")
+            }
+        }
+    }
+}
+
+/// Parses `--tmpfs` value in format `destination[:options]`, where options are
+/// mount flags or tmpfs mount data.
+pub fn parse_tmpfs(s: &str) -> Result<Volume, &'static str> {
+    let (destination, options) = s.split_once(":").unwrap_or((s, ""));
+    if destination.is_empty() {
+        return Err("Invalid tmpfs syntax. Expected in format 'destination[:options]'");
+    }
+
+    let mut volume = Volume::tmpfs(PathBuf::from(destination));
+    let mut data = vec![];
+
+    for option in options.split(',').filter(|option| !option.is_empty()) {
+        if !volume.options.apply(option) {
+            data.push(option);
+        }
+    }
+
+    volume.kind = VolumeKind::Tmpfs(data.join(","));
+
+    Ok(volume)
+}
+
+/// Parses `--mount` value in format
+/// `type=bind|volume|tmpfs,source=...,target=...[,option...]`.
+pub fn parse_mount(s: &str) -> Result<Volume, &'static str> {
+    let mut mount_type = "volume";
+    let mut source = None;
+    let mut target = None;
+    let mut options = VolumeOptions::default();
+    let mut tmpfs_data = vec![];
+
+    for field in s.split(',') {
+        let (key, value) = match field.split_once("=") {
+            Some((key, value)) => (key, Some(value)),
+            None => (field, None),
+        };
+
+        match (key, value) {
+            ("type", Some(value)) => mount_type = value,
+            ("source" | "src", Some(value)) => source = Some(value),
+            ("target" | "destination" | "dst", Some(value)) => target = Some(value),
+            ("readonly" | "ro", None | Some("true" | "1")) => options.readonly = true,
+            ("readonly" | "ro", Some("false" | "0")) => options.readonly = false,
+            ("bind-propagation", Some(value))
+                if value.ends_with("shared")
+                    || value.ends_with("slave")
+                    || value.ends_with("private") =>
+            {
+                options.apply(value);
+            }
+            ("bind-recursive", Some("enabled")) => options.recursive = true,
+            ("bind-recursive", Some("disabled")) => options.recursive = false,
+            ("volume-nocopy", None | Some("true" | "1")) => options.nocopy = true,
+            ("idmap", None | Some("true" | "1")) => options.idmap = true,
+            ("tmpfs-size", Some(value)) => match parse_size(value) {
+                Ok(size) => tmpfs_data.push(format!("size={}", size)),
+                Err(err) => return Err(err),
+            },
+            ("tmpfs-mode", Some(value)) => match u32::from_str_radix(value, 8) {
+                Ok(mode) => tmpfs_data.push(format!("mode={:o}", mode)),
+                Err(_) => return Err("Invalid tmpfs mode"),
+            },
+            _ => return Err("Invalid mount option"),
+        }
+    }
+
+    let destination = match target {
+        Some(target) if !target.is_empty() => PathBuf::from(target),
+        _ => return Err("Mount target is required"),
+    };
+
+    let kind = match (mount_type, source) {
+        ("bind", Some(source)) if source.starts_with('/') => VolumeKind::Bind,
+        ("bind", _) => return Err("Bind mount requires absolute source path"),
+        ("volume", Some(source)) if is_volume_name(source) => {
+            VolumeKind::Named(Some(source.to_string()))
+        }
+        ("volume", None) => VolumeKind::Named(None),
+        ("volume", _) => return Err("Invalid volume name"),
+        ("tmpfs", _) if options.idmap => return Err("tmpfs mount can not be idmapped"),
+        ("tmpfs", None) => VolumeKind::Tmpfs(tmpfs_data.join(",")),
+        ("tmpfs", Some(_)) => return Err("tmpfs mount does not take a source"),
+        _ => return Err("Invalid mount type. Expected one of 'bind', 'volume', 'tmpfs'"),
+    };
+
+    Ok(Volume {
+        kind,
+        source: PathBuf::from(source.unwrap_or_default()),
+        destination,
+        options,
+        copy_up: false,
+    })
+}
+
+impl Volume {
+    /// Anonymous volume created for path declared by the image.
+    pub fn anonymous(destination: PathBuf) -> Self {
+        Self {
+            kind: VolumeKind::Named(None),
+            source: PathBuf::new(),
+            destination,
+            options: VolumeOptions::default(),
+            copy_up: false,
+        }
+    }
+
+    pub fn tmpfs(destination: PathBuf) -> Self {
+        Self {
+            kind: VolumeKind::Tmpfs(String::new()),
+            source: PathBuf::from("tmpfs"),
+            destination,
+            options: VolumeOptions {
+                flags: MsFlags::MS_NOSUID | MsFlags::MS_NODEV | MsFlags::MS_NOEXEC,
+                ..VolumeOptions::default()
+            },
+            copy_up: false,
+        }
+    }
+
+    /// Name of con-managed volume once it is resolved.
+    pub fn name(&self) -> Option<&str> {
+        match &self.kind {
+            VolumeKind::Named(name) => name.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Resolves named volume to its data directory, creating the volume on
+    /// first use.
+    pub fn resolve(&mut self) -> Result<()> {
+        if let VolumeKind::Named(name) = &self.kind {
+            let (volume, created) = match name {
+                Some(name) => NamedVolume::get_or_create(name)?,
+                None => (NamedVolume::create_anonymous()?, true),
+            };
+
+            self.source = volume.data_path()?;
+            self.copy_up = created && !self.options.nocopy;
+            self.kind = VolumeKind::Named(Some(volume.name));
+        }
+
+        Ok(())
+    }
+}
+
+/// Paths are distinguished from volume names by containing a slash.
+fn is_volume_name(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with('.')
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+}
+
+pub fn volumes_dir() -> Result<PathBuf> {
+    Ok(data_root()?.join("volumes"))
+}
+
+/// Volume stored in con's data root.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NamedVolume {
+    pub name: String,
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Created for the container, removed together with it
+    #[serde(default)]
+    pub anonymous: bool,
+}
+
+impl NamedVolume {
+    pub fn create(name: &str, labels: BTreeMap<String, String>) -> Result<Self> {
+        if !is_volume_name(name) {
+            bail!("Invalid volume name {}", name);
+        }
+
+        if Self::load(name).is_ok() {
+            bail!("Volume {} already exists", name);
+        }
+
+        let volume = Self {
+            name: name.to_string(),
+            labels,
+            anonymous: false,
+        };
+        create_dir_all(volume.data_path()?)?;
+        volume.save()?;
+
+        Ok(volume)
+    }
+
+    pub fn create_anonymous() -> Result<Self> {
+        let volume = Self {
+            name: generate_id()?,
+            labels: BTreeMap::new(),
+            anonymous: true,
+        };
+        create_dir_all(volume.data_path()?)?;
+        volume.save()?;
+
+        Ok(volume)
+    }
+
+    /// Returns the volume and whether it was created.
+    pub fn get_or_create(name: &str) -> Result<(Self, bool)> {
+        match Self::load(name) {
+            Ok(volume) => Ok((volume, false)),
+            Err(_) => Ok((Self::create(name, BTreeMap::new())?, true)),
+        }
+    }
+
+    fn dir(&self) -> Result<PathBuf> {
+        Ok(volumes_dir()?.join(&self.name))
+    }
+
+    pub fn data_path(&self) -> Result<PathBuf> {
+        Ok(self.dir()?.join("_data"))
+    }
+
+    pub fn load(name: &str) -> Result<Self> {
+        let content = match read_to_string(volumes_dir()?.join(name).join("volume.json")) {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => bail!("No such volume: {}", name),
+            Err(err) => return Err(err.into()),
+        };
+
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        write(
+            self.dir()?.join("volume.json"),
+            serde_json::to_string_pretty(self)?,
+        )?;
+
+        Ok(())
+    }
+
+    pub fn list() -> Result<Vec<Self>> {
+        let entries = match read_dir(volumes_dir()?) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+
+        let mut volumes = vec![];
+        for entry in entries {
+            let path = entry?.path();
+            if path.join("volume.json").exists() {
+                let content = read_to_string(path.join("volume.json"))?;
+                volumes.push(serde_json::from_str(&content)?);
+            }
+        }
+
+        Ok(volumes)
+    }
+
+    /// Containers using the volume.
+    pub fn containers(&self) -> Result<Vec<State>> {
+        Ok(State::list()?
+            .into_iter()
+            .filter(|state| state.volumes.contains(&self.name))
+            .collect())
+    }
+
+    pub fn remove(&self) -> Result<()> {
+        if let Some(state) = self.containers()?.first() {
+            bail!("Volume {} is in use by container {}", self.name, state.name);
+        }
+
+        remove_dir_all(self.dir()?)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Kind, paths and set options of the volume.
+    fn describe(volume: &Volume) -> String {
+        let options = &volume.options;
+        let mut set = vec![];
+        for (is_set, name) in [
+            (options.readonly, "ro"),
+            (options.recursive, "rbind"),
+            (options.nocopy, "nocopy"),
+            (options.idmap, "idmap"),
+            (options.flags.contains(MsFlags::MS_NOSUID), "nosuid"),
+            (options.flags.contains(MsFlags::MS_NODEV), "nodev"),
+            (options.flags.contains(MsFlags::MS_NOEXEC), "noexec"),
+        ] {
+            if is_set {
+                set.push(name.to_string());
+            }
+        }
+        if let Some(propagation) = options.propagation {
+            set.push(format!("{:?}", propagation));
+        }
+
+        format!(
+            "{:?} {} -> {} [{}]",
+            volume.kind,
+            volume.source.display(),
+            volume.destination.display(),
+            set.join(",")
+        )
+    }
+
+    #[test]
+    fn parse_mount_values() {
+        let cases = [
+            (
+                "type=bind,source=/src,target=/dst,readonly",
+                Ok("Bind /src -> /dst [ro]"),
+            ),
+            (
+                "type=bind,src=/src,dst=/dst,ro=false,bind-recursive=enabled,idmap",
+                Ok("Bind /src -> /dst [rbind,idmap]"),
+            ),
+            (
+                "type=bind,source=/src,destination=/dst,bind-propagation=rslave",
+                Ok("Bind /src -> /dst [MS_REC | MS_SLAVE]"),
+            ),
+            (
+                "type=volume,source=data,target=/data,volume-nocopy",
+                Ok("Named(Some(\"data\")) data -> /data [nocopy]"),
+            ),
+            ("target=/data", Ok("Named(None)  -> /data []")),
+            (
+                "type=tmpfs,target=/run,tmpfs-size=64m,tmpfs-mode=1777",
+                Ok("Tmpfs(\"size=67108864,mode=1777\")  -> /run []"),
+            ),
+            (
+                "type=bind,source=src,target=/dst",
+                Err("Bind mount requires absolute source path"),
+            ),
+            (
+                "type=bind,target=/dst",
+                Err("Bind mount requires absolute source path"),
+            ),
+            (
+                "type=volume,source=/data,target=/data",
+                Err("Invalid volume name"),
+            ),
+            (
+                "type=tmpfs,source=tmp,target=/run",
+                Err("tmpfs mount does not take a source"),
+            ),
+            (
+                "type=tmpfs,target=/run,idmap",
+                Err("tmpfs mount can not be idmapped"),
+            ),
+            (
+                "type=nfs,target=/mnt",
+                Err("Invalid mount type. Expected one of 'bind', 'volume', 'tmpfs'"),
+            ),
+            ("source=data", Err("Mount target is required")),
+            ("type=volume,target=", Err("Mount target is required")),
+            ("", Err("Invalid mount option")),
+            ("target=/data,readonly=maybe", Err("Invalid mount option")),
+            (
+                "type=bind,source=/src,target=/dst,bind-propagation=none",
+                Err("Invalid mount option"),
+            ),
+            (
+                "type=tmpfs,target=/run,tmpfs-mode=999",
+                Err("Invalid tmpfs mode"),
+            ),
+            (
+                "type=tmpfs,target=/run,tmpfs-size=big",
+                Err("Invalid size. Expected number with optional suffix (k, m, g)"),
+            ),
+        ];
+
+        for (input, expected) in cases {
+            let volume = parse_mount(input).map(|volume| describe(&volume));
+            assert_eq!(volume, expected.map(String::from), "{}", input);
+        }
+    }
+
+    #[test]
+    fn parse_tmpfs_values() {
+        let cases = [
+            (
+                "/run",
+                Ok("Tmpfs(\"\") tmpfs -> /run [nosuid,nodev,noexec]"),
+            ),
+            (
+                "/run:size=64m,noexec,mode=1777,nosuid",
+                Ok("Tmpfs(\"size=64m,mode=1777\") tmpfs -> /run [nosuid,nodev,noexec]"),
+            ),
+            (
+                "/run:ro,",
+                Ok("Tmpfs(\"\") tmpfs -> /run [ro,nosuid,nodev,noexec]"),
+            ),
+            ("/run:exec,suid", Ok("Tmpfs(\"\") tmpfs -> /run [nodev]")),
+            (
+                "",
+                Err("Invalid tmpfs syntax. Expected in format 'destination[:options]'"),
+            ),
+            (
+                ":size=64m",
+                Err("Invalid tmpfs syntax. Expected in format 'destination[:options]'"),
+            ),
+        ];
+
+        for (input, expected) in cases {
+            let volume = parse_tmpfs(input).map(|volume| describe(&volume));
+            assert_eq!(volume, expected.map(String::from), "{}", input);
         }
     }
 }
@@ -0,0 +1,115 @@
+use std::{
+    env,
+    fs::{copy, create_dir_all, remove_dir_all, write},
+    path::{Path, PathBuf},
+    process::{self, Command},
+};
+
+use sha2::{Digest, Sha256};
+
+fn sha256(content: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content);
+
+    format!("{:x}", hasher.finalize())
+}
+
+/// Writes `content` to blob directory of the image at `base_path` and
+/// returns its digest.
+fn write_blob(base_path: &Path, content: &[u8]) -> String {
+    let digest = sha256(content);
+    write(base_path.join("blobs/sha256").join(&digest), content).unwrap();
+
+    format!("sha256:{}", digest)
+}
+
+/// Copies `path` from the host into `layer`, keeping its location.
+fn copy_into(layer: &Path, path: &Path) {
+    let target = layer.join(path.strip_prefix("/").unwrap());
+    create_dir_all(target.parent().unwrap()).unwrap();
+    copy(path, target).unwrap();
+}
+
+/// Builds image `name` in `dir` whose only (unpacked) layer holds host's
+/// `/bin/sh` with the libraries it is linked to and the usual mount points.
+fn build_shell_image(dir: &Path, name: &str) {
+    let base_path = dir.join(name);
+    create_dir_all(base_path.join("blobs/sha256")).unwrap();
+
+    let layer_digest = sha256(name.as_bytes());
+    let layer = base_path.join("blobs/sha256").join(&layer_digest);
+    create_dir_all(&layer).unwrap();
+
+    for dir in ["dev", "proc", "sys", "tmp"] {
+        create_dir_all(layer.join(dir)).unwrap();
+    }
+    copy_into(&layer, Path::new("/bin/sh"));
+    let ldd = Command::new("ldd").arg("/bin/sh").output().unwrap();
+    for word in String::from_utf8_lossy(&ldd.stdout).split_whitespace() {
+        if word.starts_with('/') {
+            copy_into(&layer, Path::new(word));
+        }
+    }
+
+    let configuration = format!(
+        r#"{{"architecture":"amd64","os":"linux","config":{{"Cmd":["/bin/sh"]}},"rootfs":{{"type":"layers","diff_ids":["sha256:{}"]}},"history":[]}}"#,
+        layer_digest
+    );
+    let configuration_digest = write_blob(&base_path, configuration.as_bytes());
+
+    let manifest = format!(
+        r#"{{"schemaVersion":2,"config":{{"mediaType":"application/vnd.oci.image.config.v1+json","digest":"{}","size":{}}},"layers":[{{"mediaType":"application/vnd.oci.image.layer.v1.tar+gzip","digest":"sha256:{}","size":0}}]}}"#,
+        configuration_digest,
+        configuration.len(),
+        layer_digest
+    );
+    let manifest_digest = write_blob(&base_path, manifest.as_bytes());
+
+    let index = format!(
+        r#"{{"schemaVersion":2,"manifests":[{{"mediaType":"application/vnd.oci.image.manifest.v1+json","digest":"{}","size":{}}}]}}"#,
+        manifest_digest,
+        manifest.len()
+    );
+    write(base_path.join("index.json"), index).unwrap();
+    write(
+        base_path.join("oci-layout"),
+        r#"{"imageLayoutVersion":"1.0.0"}"#,
+    )
+    .unwrap();
+}
+
+// needs newuidmap, newgidmap and cgroups set up by init.sh, run with
+// `cargo test -- --ignored`
+#[test]
+#[ignore]
+fn read_only_root_file_system() {
+    let dir: PathBuf = env::temp_dir().join(format!("con-read-only-{}", process::id()));
+    build_shell_image(&dir, "test/shell");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_con"))
+        .current_dir(&dir)
+        .args([
+            "run",
+            "--rm",
+            "--read-only",
+            "--workdir",
+            "/work/dir",
+            "test/shell",
+            "--",
+            "/bin/sh",
+            "-c",
+            "(echo > /file) 2>/dev/null || echo root read-only; \
+             echo > /tmp/file && echo tmp writable; pwd",
+        ])
+        .output()
+        .unwrap();
+    remove_dir_all(&dir).unwrap();
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert_eq!(stdout, "root read-only\ntmp writable\n/work/dir\n");
+}